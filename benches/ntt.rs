@@ -0,0 +1,42 @@
+// benchmarks of the in place NTT, from 2^10 to 2^22 elements
+// with --features parallel, the single threaded transform is benchmarked alongside
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use stark_prover_verifier::domain::Domain;
+use stark_prover_verifier::field::FieldElement;
+use stark_prover_verifier::ntt::{intt, ntt, ntt_bit_reversed};
+
+fn bench_ntt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ntt");
+    group.sample_size(10);
+
+    for log_n in (10..=22).step_by(2) {
+        let n = 1usize << log_n;
+        let root = Domain::root_of_unity(log_n);
+        // warm up the twiddle cache so only the transform is measured
+        Domain::new(log_n).inv_twiddles();
+        let values: Vec<FieldElement> = (0..n as u64).map(FieldElement::new).collect();
+
+        group.bench_with_input(BenchmarkId::new("natural", n), &values, |b, values| {
+            b.iter_batched_ref(|| values.clone(), |v| ntt(v, root), criterion::BatchSize::LargeInput)
+        });
+        group.bench_with_input(BenchmarkId::new("bit_reversed", n), &values, |b, values| {
+            b.iter_batched_ref(|| values.clone(), |v| ntt_bit_reversed(v, root), criterion::BatchSize::LargeInput)
+        });
+        group.bench_with_input(BenchmarkId::new("inverse", n), &values, |b, values| {
+            b.iter_batched_ref(|| values.clone(), |v| intt(v, root), criterion::BatchSize::LargeInput)
+        });
+
+        #[cfg(feature = "parallel")]
+        {
+            let single_thread = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+            group.bench_with_input(BenchmarkId::new("natural_single_thread", n), &values, |b, values| {
+                b.iter_batched_ref(|| values.clone(), |v| single_thread.install(|| ntt(v, root)), criterion::BatchSize::LargeInput)
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_ntt);
+criterion_main!(benches);
@@ -0,0 +1,402 @@
+// algebraic intermediate representation of a computation, the statement the prover and the
+// verifier agree on: a trace of steps rows and num_columns columns, transition constraints
+// between consecutive rows, assertions on given cells, and periodic columns of public values
+// each transition constraint holds on a domain of steps, every step but the last one by default
+// the trace may have an auxiliary segment, built by the prover from the main trace and random
+// challenges drawn after its commitment (a randomized AIR with preprocessing, RAP), e.g. the
+// running product of a permutation argument
+// the transition constraints are given either as expressions, whose degrees are inferred, or by
+// evaluate_transition along with their degrees
+
+use std::fmt;
+
+use crate::assertion::{Assertion, AssertionError};
+use crate::domain::Domain;
+use crate::expression::Expr;
+use crate::field::FieldElement;
+use crate::poly::Poly;
+use crate::subproduct_tree::SubproductTree;
+
+pub trait Air {
+    // number of rows of the trace, a power of 2
+    fn steps(&self) -> usize;
+
+    fn num_columns(&self) -> usize;
+
+    // the transition constraints on two consecutive rows, which must each be zero on the steps
+    // of its transition domain
+    fn transition_constraints(&self) -> Vec<Expr> {
+        vec![]
+    }
+
+    // degrees of the transition constraints in the trace and periodic columns
+    fn transition_degrees(&self) -> Vec<usize> {
+        self.transition_constraints().iter().map(|c| c.degree()).collect()
+    }
+
+    // the transition constraints evaluated on two consecutive rows
+    // the rows hold the main columns followed by the auxiliary columns, periodic holds the values
+    // of the periodic columns at the current step
+    fn evaluate_transition(
+        &self,
+        current: &[FieldElement],
+        next: &[FieldElement],
+        periodic: &[FieldElement],
+        challenges: &[FieldElement]
+    ) -> Vec<FieldElement> {
+        self.transition_constraints().iter().map(|c| c.eval(current, next, periodic, challenges)).collect()
+    }
+
+    // assertions on the main and auxiliary columns, indexed like the rows
+    fn assertions(&self) -> Vec<Assertion>;
+
+    fn num_aux_columns(&self) -> usize {
+        0
+    }
+
+    // number of challenges drawn after the commitment of the main trace
+    fn num_challenges(&self) -> usize {
+        0
+    }
+
+    // the auxiliary columns, of steps values each, from the main trace columns and the challenges
+    fn build_aux_trace(&self, _main: &[Vec<FieldElement>], _challenges: &[FieldElement]) -> Vec<Vec<FieldElement>> {
+        vec![]
+    }
+
+    fn periodic_columns(&self) -> Vec<PeriodicColumn> {
+        vec![]
+    }
+
+    // the steps each transition constraint applies to
+    fn transition_domains(&self) -> Vec<TransitionDomain> {
+        vec![TransitionDomain::AllButLast; self.num_transition_constraints()]
+    }
+
+    fn num_transition_constraints(&self) -> usize {
+        self.transition_degrees().len()
+    }
+
+    // largest degree of the transition constraints
+    fn constraint_degree(&self) -> usize {
+        self.transition_degrees().into_iter().max().unwrap_or(1)
+    }
+}
+
+// error returned when an air can't be proven or verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AirError {
+    // the number of steps isn't a power of 2, or is too large for the field
+    InvalidSteps(usize),
+    // evaluate_transition, or transition_constraints, gives this many constraints instead of
+    // num_transition_constraints
+    WrongNumberOfTransitionConstraints(usize),
+    // the expression of the given constraint refers to a column, periodic column or challenge
+    // that doesn't exist
+    InvalidConstraintIndex(usize),
+    // the stated degree of the given constraint is below the degree of its expression
+    UnderstatedTransitionDegree(usize),
+    // the number of transition domains doesn't match the number of transition constraints
+    WrongNumberOfTransitionDomains(usize),
+    // the transition domain of the given constraint doesn't fit the trace
+    InvalidTransitionDomain(usize),
+    InvalidAssertion(AssertionError),
+    // an assertion is on the given column, past the main and auxiliary columns
+    InvalidAssertionColumn(usize),
+    // the period of the given periodic column doesn't divide the number of steps
+    InvalidPeriodicColumn(usize),
+    // the trace has this many columns instead of num_columns
+    WrongNumberOfColumns(usize),
+    // build_aux_trace gives this many columns instead of num_aux_columns
+    WrongNumberOfAuxColumns(usize),
+    // the given column, main or auxiliary, doesn't have one value per step
+    WrongColumnLength(usize),
+    // the composition polynomial doesn't fit the evaluation domain
+    BlowupTooSmall { required: usize, extension_factor: usize },
+    // too few steps for the openings of a zk proof to be hiding
+    TooFewStepsForZk { steps: usize, required: usize },
+}
+
+impl fmt::Display for AirError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AirError::InvalidSteps(steps) => write!(f, "invalid number of steps {}", steps),
+            AirError::WrongNumberOfTransitionConstraints(len) => write!(f, "wrong number of transition constraints: {}", len),
+            AirError::InvalidConstraintIndex(i) => write!(f, "constraint {} refers to an index out of bounds", i),
+            AirError::UnderstatedTransitionDegree(i) => write!(f, "understated degree for constraint {}", i),
+            AirError::WrongNumberOfTransitionDomains(len) => write!(f, "wrong number of transition domains: {}", len),
+            AirError::InvalidTransitionDomain(i) => write!(f, "invalid transition domain for constraint {}", i),
+            AirError::InvalidAssertion(err) => write!(f, "invalid assertion: {}", err),
+            AirError::InvalidAssertionColumn(column) => write!(f, "assertion on column {} out of bounds", column),
+            AirError::InvalidPeriodicColumn(i) => write!(f, "the period of periodic column {} doesn't divide the steps", i),
+            AirError::WrongNumberOfColumns(len) => write!(f, "wrong number of trace columns: {}", len),
+            AirError::WrongNumberOfAuxColumns(len) => write!(f, "wrong number of auxiliary columns: {}", len),
+            AirError::WrongColumnLength(i) => write!(f, "column {} doesn't have one value per step", i),
+            AirError::BlowupTooSmall { required, extension_factor } => write!(
+                f,
+                "the constraints need a blowup factor of {}, but the extension factor is {}",
+                required, extension_factor
+            ),
+            AirError::TooFewStepsForZk { steps, required } => write!(
+                f,
+                "zk proofs need at least {} steps to be hiding, but the trace has {}",
+                required, steps
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AirError {}
+
+// the steps a transition constraint must be zero on, and the polynomial Z(x) vanishing on them
+// the constraint on step i reads rows i and i + 1, the last step wrapping around to the first row
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionDomain {
+    // every step, the last one constraining the last row and the first, Z(x) = x^steps - 1
+    All,
+    // every step but the last one, Z(x) = (x^steps - 1) / (x - g^(steps - 1))
+    AllButLast,
+    // steps offset, offset + stride, offset + 2 stride, ... with stride a power of 2 dividing the
+    // number of steps and offset < stride
+    // the steps form the coset g^offset <g^stride>, Z(x) = x^m - g^(offset m) with m = steps / stride
+    Every { stride: usize, offset: usize },
+    // steps start..end, Z(x) = prod (x - g^i)
+    // evaluated in O(min(end - start, steps - end + start)), from the complement when it is smaller
+    Range { start: usize, end: usize },
+}
+
+impl TransitionDomain {
+    pub fn is_valid(&self, steps: usize) -> bool {
+        match *self {
+            TransitionDomain::All | TransitionDomain::AllButLast => true,
+            TransitionDomain::Every { stride, offset } => {
+                stride.is_power_of_two() && steps.is_multiple_of(stride) && offset < stride
+            }
+            TransitionDomain::Range { start, end } => start < end && end <= steps,
+        }
+    }
+
+    // number of steps of the domain, the degree of Z
+    pub fn num_steps(&self, steps: usize) -> usize {
+        match *self {
+            TransitionDomain::All => steps,
+            TransitionDomain::AllButLast => steps - 1,
+            TransitionDomain::Every { stride, .. } => steps / stride,
+            TransitionDomain::Range { start, end } => end - start,
+        }
+    }
+
+    // the steps of the domain
+    pub fn steps(&self, steps: usize) -> Vec<usize> {
+        match *self {
+            TransitionDomain::All => (0..steps).collect(),
+            TransitionDomain::AllButLast => (0..steps - 1).collect(),
+            TransitionDomain::Every { stride, offset } => (offset..steps).step_by(stride).collect(),
+            TransitionDomain::Range { start, end } => (start..end).collect(),
+        }
+    }
+
+    // a range is vanished by the product over its steps, or by (x^steps - 1) divided by the
+    // product over the other steps, whichever is smaller
+    fn range_is_small(start: usize, end: usize, steps: usize) -> bool {
+        2 * (end - start) <= steps
+    }
+
+    fn points(indices: impl Iterator<Item = usize>, steps: usize) -> Vec<FieldElement> {
+        let g = Domain::root_of_unity(steps.trailing_zeros());
+        indices.map(|i| g.pow(i as u64)).collect()
+    }
+
+    fn product(points: &[FieldElement]) -> Poly {
+        if points.is_empty() {
+            return Poly::new(vec![FieldElement::new(1)]);
+        }
+        SubproductTree::new(points).product().clone()
+    }
+
+    // x^m - c
+    fn sparse(m: usize, c: FieldElement) -> Poly {
+        let mut coeffs = vec![FieldElement::new(0); m + 1];
+        coeffs[0] = -c;
+        coeffs[m] = FieldElement::new(1);
+        Poly::new(coeffs)
+    }
+
+    // Z as a fraction numerator / denominator of polynomials of degree <= steps
+    pub fn vanishing_fraction(&self, steps: usize) -> (Poly, Poly) {
+        let g = Domain::root_of_unity(steps.trailing_zeros());
+        let one = FieldElement::new(1);
+        match *self {
+            TransitionDomain::All => (TransitionDomain::sparse(steps, one), Poly::new(vec![one])),
+            TransitionDomain::AllButLast => {
+                (TransitionDomain::sparse(steps, one), TransitionDomain::sparse(1, g.pow(steps as u64 - 1)))
+            }
+            TransitionDomain::Every { stride, offset } => {
+                let m = steps / stride;
+                (TransitionDomain::sparse(m, g.pow((offset * m) as u64)), Poly::new(vec![one]))
+            }
+            TransitionDomain::Range { start, end } => {
+                if TransitionDomain::range_is_small(start, end, steps) {
+                    (TransitionDomain::product(&TransitionDomain::points(start..end, steps)), Poly::new(vec![one]))
+                } else {
+                    let complement = TransitionDomain::points((0..start).chain(end..steps), steps);
+                    (TransitionDomain::sparse(steps, one), TransitionDomain::product(&complement))
+                }
+            }
+        }
+    }
+
+    // Z(x), for x outside of the trace domain
+    pub fn eval_vanishing(&self, x: FieldElement, steps: usize) -> FieldElement {
+        let g = Domain::root_of_unity(steps.trailing_zeros());
+        let one = FieldElement::new(1);
+        match *self {
+            TransitionDomain::All => x.pow(steps as u64) - one,
+            TransitionDomain::AllButLast => (x.pow(steps as u64) - one) / (x - g.pow(steps as u64 - 1)),
+            TransitionDomain::Every { stride, offset } => {
+                let m = (steps / stride) as u64;
+                x.pow(m) - g.pow(offset as u64 * m)
+            }
+            TransitionDomain::Range { start, end } => {
+                if TransitionDomain::range_is_small(start, end, steps) {
+                    TransitionDomain::points(start..end, steps).iter().map(|p| x - *p).product()
+                } else {
+                    let complement: FieldElement = TransitionDomain::points((0..start).chain(end..steps), steps)
+                        .iter()
+                        .map(|p| x - *p)
+                        .product();
+                    (x.pow(steps as u64) - one) / complement
+                }
+            }
+        }
+    }
+
+    // 1 / Z over the coset offset * <root> of size domain_size, which must avoid the trace domain
+    pub fn inv_vanishing_on_coset(&self, steps: usize, domain_size: usize, offset: FieldElement) -> Vec<FieldElement> {
+        let root = Domain::root_of_unity(domain_size.trailing_zeros());
+        let (numerator, denominator) = self.vanishing_fraction(steps);
+        let numerator = Poly::coset_fft(&numerator.coeffs, offset, &root);
+        let denominator = Poly::coset_fft(&denominator.coeffs, offset, &root);
+        FieldElement::multi_inv(&numerator)
+            .iter()
+            .zip(denominator.iter())
+            .map(|(n, d)| *n * *d)
+            .collect()
+    }
+}
+
+// a column of public values repeating every k steps, e.g. the round constants of a hash
+// it is never committed: its polynomial c(x) = P(x^(steps / k)), where P interpolates the k values
+// over the subgroup of size k, is computed by the prover and the verifier alike
+// on the trace domain c(g^i) = P(w^i) with w = g^(steps / k) of order k, so c(g^i) = values[i % k]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeriodicColumn {
+    values: Vec<FieldElement>,
+    // coefficients of P, interpolated once
+    poly: Poly,
+}
+
+impl PeriodicColumn {
+    pub fn new(values: Vec<FieldElement>) -> PeriodicColumn {
+        assert!(values.len().is_power_of_two(), "the period must be a power of 2");
+        let poly = Poly::new(Poly::inv_fft(&values, &Domain::root_of_unity(values.len().trailing_zeros())));
+        PeriodicColumn { values, poly }
+    }
+
+    pub fn period(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn values(&self) -> &[FieldElement] {
+        &self.values
+    }
+
+    pub fn poly(&self) -> &Poly {
+        &self.poly
+    }
+
+    // c(x) = P(x^(steps / k)), in O(k + log steps)
+    pub fn eval(&self, x: FieldElement, steps: usize) -> FieldElement {
+        self.poly.eval(x.pow((steps / self.period()) as u64))
+    }
+
+    // evaluations over the coset offset * <g2> of size domain_size
+    // x^(steps / k) goes over the coset offset^(steps / k) * <g2^(steps / k)>, so the evaluations
+    // repeat every domain_size / (steps / k) points, and only that many are computed and returned
+    pub fn evaluate_on_coset(&self, steps: usize, domain_size: usize, offset: FieldElement) -> Vec<FieldElement> {
+        assert!(steps.is_multiple_of(self.period()), "the period must divide the number of steps");
+        let r = steps / self.period();
+        let root = Domain::root_of_unity((domain_size / r).trailing_zeros());
+        Poly::coset_fft(&self.poly.coeffs, offset.pow(r as u64), &root)
+    }
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periodic_column() {
+        let values = (0..4).map(|i| FieldElement::new(i * i + 9)).collect::<Vec<_>>();
+        let column = PeriodicColumn::new(values.clone());
+        assert!(column.poly().degree() < column.period());
+        let steps = 16;
+        let g = Domain::root_of_unity(4);
+        for i in 0..steps {
+            assert_eq!(column.eval(g.pow(i as u64), steps), values[i % 4]);
+        }
+
+        // the evaluations over the coset repeat with a period of 4 * blowup
+        let offset = FieldElement::generator();
+        let domain = Domain::new(7);
+        let evaluations = column.evaluate_on_coset(steps, domain.size(), offset);
+        assert_eq!(evaluations.len(), 32);
+        for i in [0, 5, 31, 32, 100] {
+            let x = offset * domain.powers()[i];
+            assert_eq!(evaluations[i % 32], column.eval(x, steps));
+        }
+    }
+
+    #[test]
+    fn test_transition_domains() {
+        let steps = 16;
+        let g = Domain::root_of_unity(4);
+        let offset = FieldElement::generator();
+        let domain = Domain::new(6);
+        let domains = [
+            TransitionDomain::All,
+            TransitionDomain::AllButLast,
+            TransitionDomain::Every { stride: 4, offset: 3 },
+            TransitionDomain::Range { start: 2, end: 6 },
+            TransitionDomain::Range { start: 1, end: 16 },
+        ];
+        for transition_domain in domains {
+            assert!(transition_domain.is_valid(steps));
+            let rows = transition_domain.steps(steps);
+            assert_eq!(rows.len(), transition_domain.num_steps(steps));
+
+            // Z vanishes exactly on the steps of the domain
+            let (numerator, denominator) = transition_domain.vanishing_fraction(steps);
+            let (z, remainder) = numerator.div_rem(&denominator);
+            assert!(remainder.is_zero());
+            assert_eq!(z.degree(), rows.len());
+            for i in 0..steps {
+                assert_eq!(z.eval(g.pow(i as u64)) == FieldElement::new(0), rows.contains(&i));
+            }
+
+            let x = FieldElement::new(987654321);
+            assert_eq!(transition_domain.eval_vanishing(x, steps), z.eval(x));
+            let inv_z = transition_domain.inv_vanishing_on_coset(steps, domain.size(), offset);
+            for i in [0, 7, 63] {
+                assert_eq!(inv_z[i] * z.eval(offset * domain.powers()[i]), FieldElement::new(1));
+            }
+        }
+
+        assert_eq!(TransitionDomain::Every { stride: 4, offset: 3 }.steps(steps), vec![3, 7, 11, 15]);
+        assert!(!TransitionDomain::Every { stride: 3, offset: 0 }.is_valid(steps));
+        assert!(!TransitionDomain::Every { stride: 4, offset: 4 }.is_valid(steps));
+        assert!(!TransitionDomain::Range { start: 4, end: 4 }.is_valid(steps));
+        assert!(!TransitionDomain::Range { start: 4, end: 17 }.is_valid(steps));
+    }
+}
@@ -0,0 +1,310 @@
+// boundary constraints: assertions that a trace column takes given values at given steps
+// each assertion gives the constraint (T(x) - I(x)) / Z(x), where I interpolates the asserted
+// values and Z vanishes on the asserted steps
+
+use std::fmt;
+
+use crate::domain::Domain;
+use crate::field::FieldElement;
+use crate::poly::Poly;
+use crate::subproduct_tree::SubproductTree;
+
+// error returned when an assertion doesn't fit the trace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssertionError {
+    // the stride doesn't divide the trace length, or is 0
+    InvalidStride(usize),
+    // an asserted step is past the end of the trace, or past the stride of a periodic assertion
+    StepOutOfBounds(usize),
+    // the number of values doesn't fit the asserted steps
+    InvalidNumberOfValues(usize),
+}
+
+impl fmt::Display for AssertionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssertionError::InvalidStride(stride) => write!(f, "invalid assertion stride {}", stride),
+            AssertionError::StepOutOfBounds(step) => write!(f, "asserted step {} is out of bounds", step),
+            AssertionError::InvalidNumberOfValues(len) => write!(f, "invalid number of asserted values: {}", len),
+        }
+    }
+}
+
+impl std::error::Error for AssertionError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AssertionKind {
+    // the values repeat cyclically at every stride steps, over the whole trace
+    Periodic,
+    // one value at each of the first values.len() strided steps
+    Sequence,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assertion {
+    column: usize,
+    first_step: usize,
+    stride: usize,
+    values: Vec<FieldElement>,
+    kind: AssertionKind,
+}
+
+impl Assertion {
+    // column[step] = value
+    pub fn single(column: usize, step: usize, value: FieldElement) -> Assertion {
+        Assertion::sequence(column, step, 1, vec![value])
+    }
+
+    // column[first_step + k * stride] = values[k % values.len()] for every such step of the trace
+    // the stride and the number of values must be powers of 2, and first_step < stride
+    // the verifier evaluates it in O(values.len())
+    pub fn periodic(column: usize, first_step: usize, stride: usize, values: Vec<FieldElement>) -> Assertion {
+        Assertion { column, first_step, stride, values, kind: AssertionKind::Periodic }
+    }
+
+    // column[first_step + k * stride] = values[k] for k < values.len()
+    pub fn sequence(column: usize, first_step: usize, stride: usize, values: Vec<FieldElement>) -> Assertion {
+        Assertion { column, first_step, stride, values, kind: AssertionKind::Sequence }
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    // the same assertion on another column, e.g. when the columns of an air are reordered
+    pub fn with_column(&self, column: usize) -> Assertion {
+        Assertion { column, ..self.clone() }
+    }
+
+    // check the assertion fits a trace of the given number of steps
+    pub fn validate(&self, steps: usize) -> Result<(), AssertionError> {
+        let len = self.values.len();
+        match self.kind {
+            AssertionKind::Periodic => {
+                if !self.stride.is_power_of_two() || !steps.is_multiple_of(self.stride) {
+                    return Err(AssertionError::InvalidStride(self.stride));
+                }
+                if self.first_step >= self.stride {
+                    return Err(AssertionError::StepOutOfBounds(self.first_step));
+                }
+                if !len.is_power_of_two() || !(steps / self.stride).is_multiple_of(len) {
+                    return Err(AssertionError::InvalidNumberOfValues(len));
+                }
+            }
+            AssertionKind::Sequence => {
+                if self.stride == 0 {
+                    return Err(AssertionError::InvalidStride(self.stride));
+                }
+                if len == 0 {
+                    return Err(AssertionError::InvalidNumberOfValues(len));
+                }
+                let last_step = self.first_step + (len - 1) * self.stride;
+                if last_step >= steps {
+                    return Err(AssertionError::StepOutOfBounds(last_step));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // number of asserted steps, the degree of the vanishing polynomial
+    pub fn num_steps(&self, steps: usize) -> usize {
+        match self.kind {
+            AssertionKind::Periodic => steps / self.stride,
+            AssertionKind::Sequence => self.values.len(),
+        }
+    }
+
+    // the asserted steps with their values
+    pub fn steps(&self, steps: usize) -> Vec<(usize, FieldElement)> {
+        (0..self.num_steps(steps))
+            .map(|k| (self.first_step + k * self.stride, self.values[k % self.values.len()]))
+            .collect()
+    }
+
+    // degree of the quotient (T(x) - I(x)) / Z(x), for a trace column of degree < steps
+    pub fn quotient_degree(&self, steps: usize) -> usize {
+        (steps - 1).saturating_sub(self.num_steps(steps))
+    }
+
+    // the asserted points of the trace domain, x0 w^k with x0 = g^first_step and w = g^stride
+    fn points(&self, steps: usize) -> Vec<FieldElement> {
+        let g = Domain::root_of_unity(steps.trailing_zeros());
+        let x0 = g.pow(self.first_step as u64);
+        let w = g.pow(self.stride as u64);
+        let mut x = x0;
+        let mut o = Vec::with_capacity(self.num_steps(steps));
+        for _ in 0..self.num_steps(steps) {
+            o.push(x);
+            x *= w;
+        }
+        o
+    }
+
+    // the periodic points form the coset x0 <w>, which is vanished by x^m - x0^m
+    pub fn vanishing_poly(&self, steps: usize) -> Poly {
+        match self.kind {
+            AssertionKind::Periodic => {
+                let m = self.num_steps(steps);
+                let x0 = Domain::root_of_unity(steps.trailing_zeros()).pow(self.first_step as u64);
+                let mut coeffs = vec![FieldElement::new(0); m + 1];
+                coeffs[0] = -x0.pow(m as u64);
+                coeffs[m] = FieldElement::new(1);
+                Poly::new(coeffs)
+            }
+            AssertionKind::Sequence => SubproductTree::new(&self.points(steps)).product().clone(),
+        }
+    }
+
+    pub fn eval_vanishing(&self, x: FieldElement, steps: usize) -> FieldElement {
+        match self.kind {
+            AssertionKind::Periodic => {
+                let m = self.num_steps(steps) as u64;
+                let x0 = Domain::root_of_unity(steps.trailing_zeros()).pow(self.first_step as u64);
+                x.pow(m) - x0.pow(m)
+            }
+            AssertionKind::Sequence => self.points(steps).iter().map(|p| x - *p).product(),
+        }
+    }
+
+    // the values of a periodic assertion repeat every L = values.len() points, so its interpolant
+    // is I(x) = J(x^r) with r = m / L, where J interpolates the values over the L points
+    // (x0 w^j)^r = x0^r (w^r)^j, a coset of the subgroup of size L
+    fn periodic_base(&self, steps: usize) -> (Poly, usize) {
+        let len = self.values.len();
+        let r = self.num_steps(steps) / len;
+        let x0 = Domain::root_of_unity(steps.trailing_zeros()).pow(self.first_step as u64);
+        let root = Domain::root_of_unity(len.trailing_zeros());
+        (Poly::new(Poly::coset_inv_fft(&self.values, x0.pow(r as u64), &root)), r)
+    }
+
+    // the polynomial of degree < num_steps taking the asserted values at the asserted steps
+    pub fn interpolant(&self, steps: usize) -> Poly {
+        match self.kind {
+            AssertionKind::Periodic => {
+                let (base, r) = self.periodic_base(steps);
+                let mut coeffs = vec![FieldElement::new(0); base.coeffs.len() * r];
+                for (i, c) in base.coeffs.iter().enumerate() {
+                    coeffs[i * r] = *c;
+                }
+                Poly::new(coeffs)
+            }
+            AssertionKind::Sequence => Poly::fast_interpolation(&self.points(steps), &self.values),
+        }
+    }
+
+    pub fn eval_interpolant(&self, x: FieldElement, steps: usize) -> FieldElement {
+        match self.kind {
+            AssertionKind::Periodic => {
+                let (base, r) = self.periodic_base(steps);
+                base.eval(x.pow(r as u64))
+            }
+            AssertionKind::Sequence => self.eval_sequence_interpolant(x, steps),
+        }
+    }
+
+    // barycentric evaluation of the interpolant of a sequence, in O(n) for n values
+    // I(x) = Z(x) * sum y_k / (Z'(x_k) (x - x_k)), and with x_k = x0 w^k
+    // Z'(x_k) = prod_(j != k) (x_k - x_j) = x_k^(n-1) prod_(i=1..k) (1 - w^-i) prod_(i=1..n-1-k) (1 - w^i)
+    fn eval_sequence_interpolant(&self, x: FieldElement, steps: usize) -> FieldElement {
+        let points = self.points(steps);
+        if let Some(k) = points.iter().position(|p| *p == x) {
+            return self.values[k];
+        }
+
+        let n = points.len();
+        let one = FieldElement::new(1);
+        let w = Domain::root_of_unity(steps.trailing_zeros()).pow(self.stride as u64);
+        let w_inv = w.inv();
+        let (mut below, mut above) = (vec![one], vec![one]);
+        let (mut w_i, mut w_inv_i) = (one, one);
+        for i in 1..n {
+            w_i *= w;
+            w_inv_i *= w_inv;
+            below.push(below[i - 1] * (one - w_inv_i));
+            above.push(above[i - 1] * (one - w_i));
+        }
+
+        let w_n = w.pow(n as u64 - 1);
+        let mut x_k_n = points[0].pow(n as u64 - 1);
+        let mut denominators = Vec::with_capacity(n);
+        for k in 0..n {
+            denominators.push(x_k_n * below[k] * above[n - 1 - k] * (x - points[k]));
+            x_k_n *= w_n;
+        }
+        let vanishing = points.iter().map(|p| x - *p).product::<FieldElement>();
+        let sum = FieldElement::multi_inv(&denominators)
+            .iter()
+            .zip(self.values.iter())
+            .map(|(d, y)| *d * *y)
+            .sum::<FieldElement>();
+        vanishing * sum
+    }
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(n: u64) -> Vec<FieldElement> {
+        (0..n).map(|i| FieldElement::new(i * 7 + 3)).collect()
+    }
+
+    fn check(assertion: &Assertion, steps: usize) {
+        assert_eq!(assertion.validate(steps), Ok(()));
+        let g = Domain::root_of_unity(steps.trailing_zeros());
+        let interpolant = assertion.interpolant(steps);
+        let vanishing = assertion.vanishing_poly(steps);
+        assert!(interpolant.degree() < assertion.num_steps(steps));
+        assert_eq!(vanishing.degree(), assertion.num_steps(steps));
+        for (step, value) in assertion.steps(steps) {
+            let x = g.pow(step as u64);
+            assert_eq!(interpolant.eval(x), value);
+            assert_eq!(vanishing.eval(x), FieldElement::new(0));
+        }
+        // the succinct evaluations agree with the polynomials
+        let z = FieldElement::new(987654321);
+        assert_eq!(assertion.eval_interpolant(z, steps), interpolant.eval(z));
+        assert_eq!(assertion.eval_vanishing(z, steps), vanishing.eval(z));
+    }
+
+    #[test]
+    fn test_single() {
+        let assertion = Assertion::single(1, 5, FieldElement::new(42));
+        check(&assertion, 16);
+        assert_eq!(assertion.steps(16), vec![(5, FieldElement::new(42))]);
+        assert_eq!(assertion.quotient_degree(16), 14);
+    }
+
+    #[test]
+    fn test_periodic() {
+        check(&Assertion::periodic(0, 3, 4, values(1)), 32);
+        let assertion = Assertion::periodic(0, 1, 2, values(4));
+        check(&assertion, 32);
+        assert_eq!(assertion.num_steps(32), 16);
+        assert_eq!(assertion.steps(32)[5], (11, values(4)[1]));
+    }
+
+    #[test]
+    fn test_sequence() {
+        let assertion = Assertion::sequence(2, 1, 3, values(5));
+        check(&assertion, 16);
+        assert_eq!(assertion.steps(16)[4], (13, values(5)[4]));
+        check(&Assertion::sequence(0, 0, 1, values(32)), 32);
+
+        // the barycentric evaluation at an asserted point is the asserted value
+        let g = Domain::root_of_unity(4);
+        assert_eq!(assertion.eval_interpolant(g.pow(7), 16), values(5)[2]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(Assertion::single(0, 16, FieldElement::new(1)).validate(16), Err(AssertionError::StepOutOfBounds(16)));
+        assert_eq!(Assertion::periodic(0, 0, 3, values(1)).validate(16), Err(AssertionError::InvalidStride(3)));
+        assert_eq!(Assertion::periodic(0, 4, 4, values(1)).validate(16), Err(AssertionError::StepOutOfBounds(4)));
+        assert_eq!(Assertion::periodic(0, 0, 4, values(3)).validate(16), Err(AssertionError::InvalidNumberOfValues(3)));
+        assert_eq!(Assertion::sequence(0, 2, 5, values(4)).validate(16), Err(AssertionError::StepOutOfBounds(17)));
+        assert_eq!(Assertion::sequence(0, 2, 5, vec![]).validate(16), Err(AssertionError::InvalidNumberOfValues(0)));
+    }
+}
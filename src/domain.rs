@@ -0,0 +1,219 @@
+// precomputed roots of unity and twiddle tables for the 2-adic subgroups of the field
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::field::{FieldElement, TWO_ADICITY};
+
+// Domain is the multiplicative subgroup of size 2^log_size, generated by the canonical
+// 2^log_size-th root of unity 7^((p-1)/2^log_size)
+// domains of up to 2^MAX_CACHED_LOG_SIZE points are cached, so the powers of a given root are
+// computed only once and shared between the FFT, FRI and the prover
+// the cache holds at most one domain per size, so less than 2^(MAX_CACHED_LOG_SIZE + 1) points in
+// all, each with up to three tables of powers, and larger domains are computed on every call
+// 2^22 covers the largest transforms of the benchmarks
+pub struct Domain {
+    log_size: u32,
+    generator: FieldElement,
+    powers: Vec<FieldElement>,
+    inv_powers: OnceLock<Vec<FieldElement>>,
+    powers_bit_reversed: OnceLock<Vec<FieldElement>>,
+}
+
+pub const MAX_CACHED_LOG_SIZE: u32 = 22;
+
+fn cache() -> &'static Mutex<HashMap<u32, Arc<Domain>>> {
+    static CACHE: OnceLock<Mutex<HashMap<u32, Arc<Domain>>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// reverse the log_size lower bits of index
+pub fn bit_reverse_index(index: usize, log_size: u32) -> usize {
+    if log_size == 0 {
+        return 0;
+    }
+    index.reverse_bits() >> (usize::BITS - log_size)
+}
+
+// compute [1, r, r^2, ..., r^(n-1)]
+fn powers_of(r: FieldElement, n: usize) -> Vec<FieldElement> {
+    let mut o = Vec::with_capacity(n);
+    let mut x = FieldElement::new(1);
+    for _ in 0..n {
+        o.push(x);
+        x *= r;
+    }
+    o
+}
+
+impl Domain {
+    // the canonical 2^log_size-th root of unity
+    pub fn root_of_unity(log_size: u32) -> FieldElement {
+        assert!(log_size <= TWO_ADICITY, "the field has no subgroup of size 2^{}", log_size);
+        FieldElement::nth_root_of_unity(1u64 << log_size)
+    }
+
+    // get the cached domain of size 2^log_size, computing it on first use
+    pub fn new(log_size: u32) -> Arc<Domain> {
+        if log_size > MAX_CACHED_LOG_SIZE {
+            return Arc::new(Domain::compute(log_size));
+        }
+        if let Some(domain) = cache().lock().unwrap().get(&log_size) {
+            return domain.clone();
+        }
+
+        // compute outside of the lock, so big domains don't block the small ones
+        let domain = Arc::new(Domain::compute(log_size));
+        cache().lock().unwrap().entry(log_size).or_insert(domain).clone()
+    }
+
+    fn compute(log_size: u32) -> Domain {
+        let generator = Domain::root_of_unity(log_size);
+        Domain {
+            log_size,
+            generator,
+            powers: powers_of(generator, 1 << log_size),
+            inv_powers: OnceLock::new(),
+            powers_bit_reversed: OnceLock::new(),
+        }
+    }
+
+    // powers of root until they cycle back to 1, from the domain of a canonical root of unity
+    pub fn power_cycle(root: FieldElement) -> Vec<FieldElement> {
+        match Domain::from_root(root) {
+            Some(domain) => domain.powers().to_vec(),
+            None => FieldElement::get_power_cycle(root),
+        }
+    }
+
+    // get the domain generated by root, if root is a canonical root of unity
+    pub fn from_root(root: FieldElement) -> Option<Arc<Domain>> {
+        let mut x = root;
+        for log_size in 0..=TWO_ADICITY {
+            if x == FieldElement::new(1) {
+                if root == Domain::root_of_unity(log_size) {
+                    return Some(Domain::new(log_size));
+                }
+                return None;
+            }
+            x *= x;
+        }
+        None
+    }
+
+    pub fn log_size(&self) -> u32 {
+        self.log_size
+    }
+
+    pub fn size(&self) -> usize {
+        1 << self.log_size
+    }
+
+    pub fn generator(&self) -> FieldElement {
+        self.generator
+    }
+
+    // powers of the generator in natural order [1, g, g^2, ...]
+    pub fn powers(&self) -> &[FieldElement] {
+        &self.powers
+    }
+
+    // powers of the inverse of the generator [1, g^-1, g^-2, ...]
+    pub fn inv_powers(&self) -> &[FieldElement] {
+        self.inv_powers.get_or_init(|| {
+            let n = self.size();
+            (0..n).map(|i| self.powers[(n - i) % n]).collect()
+        })
+    }
+
+    // powers of the generator in bit reversed order, the i-th element is g^bitrev(i)
+    pub fn powers_bit_reversed(&self) -> &[FieldElement] {
+        self.powers_bit_reversed.get_or_init(|| {
+            (0..self.size()).map(|i| self.powers[bit_reverse_index(i, self.log_size)]).collect()
+        })
+    }
+
+    // twiddle factors of a size 2^log_size FFT, the first half of the powers
+    pub fn twiddles(&self) -> &[FieldElement] {
+        &self.powers[..self.size() / 2]
+    }
+
+    // twiddle factors of a size 2^log_size inverse FFT
+    pub fn inv_twiddles(&self) -> &[FieldElement] {
+        &self.inv_powers()[..self.size() / 2]
+    }
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_of_unity() {
+        let base:u64 = 2;
+        assert_eq!(Domain::root_of_unity(32), FieldElement::nth_root_of_unity(base.pow(32)));
+        assert_eq!(Domain::root_of_unity(0), FieldElement::new(1));
+        for log_size in 1..=32 {
+            let g = Domain::root_of_unity(log_size);
+            assert_eq!(g.pow(1 << (log_size - 1)), -FieldElement::new(1));
+        }
+    }
+
+    #[test]
+    fn test_powers() {
+        let domain = Domain::new(3);
+        let g = FieldElement::nth_root_of_unity(8);
+        assert_eq!(domain.size(), 8);
+        assert_eq!(domain.generator(), g);
+        let expected = (0..8).map(|i| g.pow(i)).collect::<Vec<_>>();
+        assert_eq!(domain.powers(), expected.as_slice());
+        for i in 0..8 {
+            assert_eq!(domain.powers()[i] * domain.inv_powers()[i], FieldElement::new(1));
+        }
+        assert_eq!(domain.twiddles(), &domain.powers()[..4]);
+    }
+
+    #[test]
+    fn test_powers_bit_reversed() {
+        let domain = Domain::new(3);
+        let p = domain.powers();
+        let expected = [p[0], p[4], p[2], p[6], p[1], p[5], p[3], p[7]];
+        assert_eq!(domain.powers_bit_reversed(), &expected);
+    }
+
+    #[test]
+    fn test_cached() {
+        let a = Domain::new(4);
+        let b = Domain::new(4);
+        assert!(Arc::ptr_eq(&a, &b));
+
+        // larger domains aren't kept
+        let a = Domain::new(MAX_CACHED_LOG_SIZE + 1);
+        assert!(!cache().lock().unwrap().contains_key(&(MAX_CACHED_LOG_SIZE + 1)));
+        assert_eq!(a.powers()[1], Domain::root_of_unity(MAX_CACHED_LOG_SIZE + 1));
+    }
+
+    #[test]
+    fn test_power_cycle() {
+        let g = Domain::root_of_unity(3);
+        assert_eq!(Domain::power_cycle(g), FieldElement::get_power_cycle(g));
+        let r = g * g * g;
+        assert_eq!(Domain::power_cycle(r), FieldElement::get_power_cycle(r));
+    }
+
+    #[test]
+    fn test_from_root() {
+        let g = Domain::root_of_unity(5);
+        assert_eq!(Domain::from_root(g).unwrap().log_size(), 5);
+        assert!(Domain::from_root(g * g * g).is_none());
+        assert!(Domain::from_root(FieldElement::new(2)).is_none());
+    }
+
+    #[test]
+    fn test_bit_reverse_index() {
+        assert_eq!(bit_reverse_index(1, 3), 4);
+        assert_eq!(bit_reverse_index(6, 3), 3);
+        assert_eq!(bit_reverse_index(0, 0), 0);
+    }
+}
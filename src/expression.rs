@@ -0,0 +1,189 @@
+// transition constraints as expression trees over the current and next rows of the trace, the
+// periodic columns and the challenges of the auxiliary segment
+// their degree in the trace and periodic columns is inferred, so that an air built from them
+// can't understate the degrees the prover relies on
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::field::FieldElement;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Constant(FieldElement),
+    // column of the current row, main then auxiliary columns
+    Current(usize),
+    // column of the next row
+    Next(usize),
+    Periodic(usize),
+    Challenge(usize),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Neg(Box<Expr>),
+    Pow(Box<Expr>, u64),
+}
+
+impl Expr {
+    pub fn constant(value: FieldElement) -> Expr {
+        Expr::Constant(value)
+    }
+
+    pub fn current(column: usize) -> Expr {
+        Expr::Current(column)
+    }
+
+    pub fn next(column: usize) -> Expr {
+        Expr::Next(column)
+    }
+
+    pub fn periodic(column: usize) -> Expr {
+        Expr::Periodic(column)
+    }
+
+    pub fn challenge(index: usize) -> Expr {
+        Expr::Challenge(index)
+    }
+
+    pub fn pow(self, exponent: u64) -> Expr {
+        Expr::Pow(Box::new(self), exponent)
+    }
+
+    // degree in the trace and periodic columns, constants and challenges having degree 0
+    pub fn degree(&self) -> usize {
+        match self {
+            Expr::Constant(_) | Expr::Challenge(_) => 0,
+            Expr::Current(_) | Expr::Next(_) | Expr::Periodic(_) => 1,
+            Expr::Add(a, b) | Expr::Sub(a, b) => a.degree().max(b.degree()),
+            Expr::Mul(a, b) => a.degree() + b.degree(),
+            Expr::Neg(a) => a.degree(),
+            Expr::Pow(a, e) => a.degree() * *e as usize,
+        }
+    }
+
+    // whether the columns, periodic columns and challenges the expression refers to all exist
+    pub fn fits(&self, num_columns: usize, num_periodic: usize, num_challenges: usize) -> bool {
+        let fits = |e: &Expr| e.fits(num_columns, num_periodic, num_challenges);
+        match self {
+            Expr::Constant(_) => true,
+            Expr::Current(i) | Expr::Next(i) => *i < num_columns,
+            Expr::Periodic(i) => *i < num_periodic,
+            Expr::Challenge(i) => *i < num_challenges,
+            Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) => fits(a) && fits(b),
+            Expr::Neg(a) | Expr::Pow(a, _) => fits(a),
+        }
+    }
+
+    // the same expression with the trace columns renumbered, e.g. when an air is embedded in a
+    // wider trace
+    pub fn map_columns<F: Fn(usize) -> usize>(&self, f: &F) -> Expr {
+        let map = |e: &Expr| Box::new(e.map_columns(f));
+        match self {
+            Expr::Current(i) => Expr::Current(f(*i)),
+            Expr::Next(i) => Expr::Next(f(*i)),
+            Expr::Constant(_) | Expr::Periodic(_) | Expr::Challenge(_) => self.clone(),
+            Expr::Add(a, b) => Expr::Add(map(a), map(b)),
+            Expr::Sub(a, b) => Expr::Sub(map(a), map(b)),
+            Expr::Mul(a, b) => Expr::Mul(map(a), map(b)),
+            Expr::Neg(a) => Expr::Neg(map(a)),
+            Expr::Pow(a, e) => Expr::Pow(map(a), *e),
+        }
+    }
+
+    pub fn eval(
+        &self,
+        current: &[FieldElement],
+        next: &[FieldElement],
+        periodic: &[FieldElement],
+        challenges: &[FieldElement]
+    ) -> FieldElement {
+        let eval = |e: &Expr| e.eval(current, next, periodic, challenges);
+        match self {
+            Expr::Constant(c) => *c,
+            Expr::Current(i) => current[*i],
+            Expr::Next(i) => next[*i],
+            Expr::Periodic(i) => periodic[*i],
+            Expr::Challenge(i) => challenges[*i],
+            Expr::Add(a, b) => eval(a) + eval(b),
+            Expr::Sub(a, b) => eval(a) - eval(b),
+            Expr::Mul(a, b) => eval(a) * eval(b),
+            Expr::Neg(a) => -eval(a),
+            Expr::Pow(a, e) => eval(a).pow(*e),
+        }
+    }
+}
+
+impl From<FieldElement> for Expr {
+    fn from(value: FieldElement) -> Expr {
+        Expr::Constant(value)
+    }
+}
+
+impl Add for Expr {
+    type Output = Expr;
+    fn add(self, other: Expr) -> Expr {
+        Expr::Add(Box::new(self), Box::new(other))
+    }
+}
+
+impl Sub for Expr {
+    type Output = Expr;
+    fn sub(self, other: Expr) -> Expr {
+        Expr::Sub(Box::new(self), Box::new(other))
+    }
+}
+
+impl Mul for Expr {
+    type Output = Expr;
+    fn mul(self, other: Expr) -> Expr {
+        Expr::Mul(Box::new(self), Box::new(other))
+    }
+}
+
+impl Neg for Expr {
+    type Output = Expr;
+    fn neg(self) -> Expr {
+        Expr::Neg(Box::new(self))
+    }
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_degree() {
+        let x = Expr::current(0);
+        assert_eq!(Expr::constant(FieldElement::new(3)).degree(), 0);
+        assert_eq!((Expr::next(0) - x.clone().pow(3) - Expr::periodic(0)).degree(), 3);
+        assert_eq!((x.clone() * Expr::challenge(0) + Expr::challenge(1)).degree(), 1);
+        assert_eq!((-(x.clone() * Expr::next(1))).pow(2).degree(), 4);
+    }
+
+    #[test]
+    fn test_eval() {
+        let e = Expr::next(0) * (Expr::challenge(0) - Expr::current(1)) - Expr::current(0).pow(2) + Expr::periodic(0)
+            + FieldElement::new(7).into();
+        let v = |x: u64| FieldElement::new(x);
+        let value = e.eval(&[v(2), v(3)], &[v(5), v(0)], &[v(11)], &[v(13)]);
+        assert_eq!(value, v(5 * 10 - 4 + 11 + 7));
+    }
+
+    #[test]
+    fn test_fits() {
+        let e = Expr::next(1) * Expr::challenge(0) - Expr::current(0).pow(2) + Expr::periodic(2);
+        assert!(e.fits(2, 3, 1));
+        assert!(!e.fits(1, 3, 1));
+        assert!(!e.fits(2, 2, 1));
+        assert!(!e.fits(2, 3, 0));
+        assert!(Expr::constant(FieldElement::new(1)).fits(0, 0, 0));
+    }
+
+    #[test]
+    fn test_map_columns() {
+        let e = Expr::next(0) * Expr::challenge(0) - Expr::current(1).pow(2) + Expr::periodic(0);
+        let shifted = e.map_columns(&|c| c + 2);
+        assert_eq!(shifted, Expr::next(2) * Expr::challenge(0) - Expr::current(3).pow(2) + Expr::periodic(0));
+        assert_eq!(shifted.degree(), e.degree());
+    }
+}
@@ -1,9 +1,13 @@
 // basic modular arithmetic for field elements
 
-use std::env::temp_dir;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter::{Product, Sum};
 use std::ops::{Add, Sub, Mul, Div, Neg, AddAssign, SubAssign, MulAssign, DivAssign, Rem};
 
-use std::hash::{Hash, Hasher};
+use rand_core::RngCore;
+use subtle::{Choice, ConditionallySelectable, ConstantTimeEq};
 
 
     // FieldElement is a wrapper around a u64 that represents a field element
@@ -23,7 +27,56 @@ use std::hash::{Hash, Hasher};
 // declare a constant for p
 pub const P: u64 = 0xffffffff00000001;
 
-#[derive(Copy, Clone, Debug)]
+// 2^64 mod p = 2^32 - 1
+const EPSILON: u64 = 0xffffffff;
+
+// p - 1 = 2^32 * (2^32 - 1)
+pub const TWO_ADICITY: u32 = 32;
+const ODD_FACTOR: u64 = 0xffffffff;
+
+// reduce a 128 bit value modulo p without branching on the value,
+// using 2^64 = 2^32 - 1 and 2^96 = -1 mod p
+fn reduce128(x: u128) -> u64 {
+    let lo = x as u64;
+    let hi = (x >> 64) as u64;
+    let hi_hi = hi >> 32;
+    let hi_lo = hi & EPSILON;
+
+    // lo - hi_hi, if it wraps around we added 2^64, remove it as 2^32 - 1
+    let (t0, borrow) = lo.overflowing_sub(hi_hi);
+    let t0 = t0.wrapping_sub(EPSILON * (borrow as u64));
+
+    // + hi_lo * (2^32 - 1), if it wraps around we removed 2^64, add it back as 2^32 - 1
+    let t1 = hi_lo * EPSILON;
+    let (t2, carry) = t0.overflowing_add(t1);
+    let t2 = t2.wrapping_add(EPSILON * (carry as u64));
+
+    // t2 < 2^64 < 2p, a single conditional subtraction is enough
+    let mask = ((t2 >= P) as u64).wrapping_neg();
+    t2.wrapping_sub(P & mask)
+}
+
+// error returned when decoding a field element from bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    // the encoding is not exactly 8 bytes long
+    InvalidLength(usize),
+    // the encoded value is not reduced modulo p
+    NonCanonical(u64),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidLength(len) => write!(f, "invalid field element encoding length: expected 8 bytes, got {}", len),
+            DecodeError::NonCanonical(value) => write!(f, "non canonical field element encoding: {:#x} >= p", value),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Copy, Clone, Debug, Default)]
 pub struct FieldElement {
     value: u64,
 }
@@ -35,6 +88,51 @@ impl PartialEq for FieldElement {
     }
 }
 
+impl Eq for FieldElement {}
+
+// the value is always reduced, so hashing and ordering it is consistent with equality
+impl Hash for FieldElement {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+impl PartialOrd for FieldElement {
+    fn partial_cmp(&self, other: &FieldElement) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FieldElement {
+    fn cmp(&self, other: &FieldElement) -> Ordering {
+        self.value.cmp(&other.value)
+    }
+}
+
+impl fmt::Display for FieldElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl From<u64> for FieldElement {
+    fn from(value: u64) -> FieldElement {
+        FieldElement::new(value)
+    }
+}
+
+impl From<u32> for FieldElement {
+    fn from(value: u32) -> FieldElement {
+        FieldElement::new(value as u64)
+    }
+}
+
+impl From<bool> for FieldElement {
+    fn from(value: bool) -> FieldElement {
+        FieldElement::new(value as u64)
+    }
+}
+
 impl Add<FieldElement> for FieldElement {
     type Output = FieldElement;
 
@@ -55,14 +153,14 @@ impl Mul<FieldElement> for FieldElement {
     type Output = FieldElement;
 
     fn mul(self, other: FieldElement) -> FieldElement {
-        let res = (self.value as u128 * other.value as u128) % P as u128 ;
-        FieldElement::new(res.try_into().unwrap())
+        FieldElement { value: reduce128(self.value as u128 * other.value as u128) }
     }
 }
 
 impl Div<FieldElement> for FieldElement {
     type Output = FieldElement;
 
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn div(self, other: FieldElement) -> FieldElement {
         self * other.inv()
     }
@@ -86,6 +184,97 @@ impl Neg for FieldElement {
     }
 }
 
+impl Neg for &FieldElement {
+    type Output = FieldElement;
+
+    fn neg(self) -> FieldElement {
+        -*self
+    }
+}
+
+// implement the binary operators on references and the assign operators
+// on top of the by value implementations above
+macro_rules! forward_ops {
+    ($op:ident, $method:ident, $op_assign:ident, $method_assign:ident) => {
+        impl $op<&FieldElement> for FieldElement {
+            type Output = FieldElement;
+
+            fn $method(self, other: &FieldElement) -> FieldElement {
+                $op::$method(self, *other)
+            }
+        }
+
+        impl $op<FieldElement> for &FieldElement {
+            type Output = FieldElement;
+
+            fn $method(self, other: FieldElement) -> FieldElement {
+                $op::$method(*self, other)
+            }
+        }
+
+        impl $op<&FieldElement> for &FieldElement {
+            type Output = FieldElement;
+
+            fn $method(self, other: &FieldElement) -> FieldElement {
+                $op::$method(*self, *other)
+            }
+        }
+
+        impl $op_assign<FieldElement> for FieldElement {
+            fn $method_assign(&mut self, other: FieldElement) {
+                *self = $op::$method(*self, other);
+            }
+        }
+
+        impl $op_assign<&FieldElement> for FieldElement {
+            fn $method_assign(&mut self, other: &FieldElement) {
+                *self = $op::$method(*self, *other);
+            }
+        }
+    };
+}
+
+forward_ops!(Add, add, AddAssign, add_assign);
+forward_ops!(Sub, sub, SubAssign, sub_assign);
+forward_ops!(Mul, mul, MulAssign, mul_assign);
+forward_ops!(Div, div, DivAssign, div_assign);
+
+impl Sum for FieldElement {
+    fn sum<I: Iterator<Item = FieldElement>>(iter: I) -> FieldElement {
+        iter.fold(FieldElement::new(0), |acc, x| acc + x)
+    }
+}
+
+impl<'a> Sum<&'a FieldElement> for FieldElement {
+    fn sum<I: Iterator<Item = &'a FieldElement>>(iter: I) -> FieldElement {
+        iter.fold(FieldElement::new(0), |acc, x| acc + x)
+    }
+}
+
+impl Product for FieldElement {
+    fn product<I: Iterator<Item = FieldElement>>(iter: I) -> FieldElement {
+        iter.fold(FieldElement::new(1), |acc, x| acc * x)
+    }
+}
+
+impl<'a> Product<&'a FieldElement> for FieldElement {
+    fn product<I: Iterator<Item = &'a FieldElement>>(iter: I) -> FieldElement {
+        iter.fold(FieldElement::new(1), |acc, x| acc * x)
+    }
+}
+
+impl ConstantTimeEq for FieldElement {
+    fn ct_eq(&self, other: &FieldElement) -> Choice {
+        self.value.ct_eq(&other.value)
+    }
+}
+
+impl ConditionallySelectable for FieldElement {
+    fn conditional_select(a: &FieldElement, b: &FieldElement, choice: Choice) -> FieldElement {
+        FieldElement { value: u64::conditional_select(&a.value, &b.value, choice) }
+    }
+}
+
 impl FieldElement {
     // new creates a new FieldElement from a u64
     pub fn new(value: u64) -> FieldElement {
@@ -93,8 +282,14 @@ impl FieldElement {
     }
 
 
+    // generator of the multiplicative group, also used as the offset of the
+    // evaluation cosets since it is outside of every 2-adic subgroup
+    pub fn generator() -> FieldElement {
+        FieldElement::new(7)
+    }
+
     pub fn nth_root_of_unity(n: u64) -> FieldElement {
-        FieldElement::new(7).pow((P - 1) / n)
+        FieldElement::generator().pow((P - 1) / n)
     }
 
     pub fn get_power_cycle(r: FieldElement) -> Vec<FieldElement> {
@@ -106,10 +301,41 @@ impl FieldElement {
         o
     }
 
+    // random samples a uniform field element by rejecting u64 values >= p
+    pub fn random(rng: &mut impl RngCore) -> FieldElement {
+        loop {
+            let value = rng.next_u64();
+            if value < P {
+                return FieldElement { value };
+            }
+        }
+    }
+
     pub fn to_bytes(&self) -> [u8; 8] {
         self.value.to_be_bytes()
     }
 
+    // from_bytes is the inverse of to_bytes, it rejects encodings which are not
+    // exactly 8 bytes long or whose value is not reduced modulo p
+    pub fn from_bytes(bytes: &[u8]) -> Result<FieldElement, DecodeError> {
+        let bytes: [u8; 8] = bytes.try_into().map_err(|_| DecodeError::InvalidLength(bytes.len()))?;
+        let value = u64::from_be_bytes(bytes);
+        if value >= P {
+            return Err(DecodeError::NonCanonical(value));
+        }
+        Ok(FieldElement { value })
+    }
+
+    // encode each element of a slice, e.g. to build merkle leaves
+    pub fn batch_to_bytes(values: &[FieldElement]) -> Vec<Vec<u8>> {
+        values.iter().map(|x| x.to_bytes().to_vec()).collect()
+    }
+
+    // decode each encoding of a slice, failing on the first invalid one
+    pub fn batch_from_bytes<T: AsRef<[u8]>>(bytes: &[T]) -> Result<Vec<FieldElement>, DecodeError> {
+        bytes.iter().map(|x| FieldElement::from_bytes(x.as_ref())).collect()
+    }
+
     // pow computes the exponentiation of a FieldElement by using the binary exponentiation algorithm
     pub fn pow(&self, exp: u64) -> FieldElement {
         let mut result = FieldElement::new(1);
@@ -117,17 +343,107 @@ impl FieldElement {
         let mut exp = exp;
         while exp > 0 {
             if exp % 2 == 1 {
-                result = result * FieldElement::new(base.try_into().unwrap());
+                result *= FieldElement::new(base.try_into().unwrap());
             }
-            exp = exp >> 1;
+            exp >>= 1;
             base = (base * base) % (P as u128);
         }
-        result.try_into().unwrap()
+        result
     }
 
+    // pow_ct computes the exponentiation in constant time: all 64 bits of the exponent
+    // are processed and the multiplication is always performed, the result being selected
+    // with a constant time conditional move
+    pub fn pow_ct(&self, exp: u64) -> FieldElement {
+        let mut result = FieldElement::new(1);
+        let mut base = *self;
+        for i in 0..64 {
+            let bit = Choice::from(((exp >> i) & 1) as u8);
+            let product = result * base;
+            result.conditional_assign(&product, bit);
+            base = base * base;
+        }
+        result
+    }
 
+    // square self k times, i.e. compute self^(2^k)
+    fn exp_power_of_2(&self, k: usize) -> FieldElement {
+        let mut result = *self;
+        for _ in 0..k {
+            result = result * result;
+        }
+        result
+    }
 
-    
+    // inv_ct computes the multiplicative inverse in constant time using Fermat's little theorem,
+    // x^(p-2) with a fixed addition chain for p - 2 = 2^64 - 2^32 - 1
+    // the inverse of zero is zero
+    pub fn inv_ct(&self) -> FieldElement {
+        // t_k = self^(2^k - 1)
+        let t1 = *self;
+        let t2 = t1.exp_power_of_2(1) * t1;
+        let t3 = t2.exp_power_of_2(1) * t1;
+        let t6 = t3.exp_power_of_2(3) * t3;
+        let t12 = t6.exp_power_of_2(6) * t6;
+        let t24 = t12.exp_power_of_2(12) * t12;
+        let t30 = t24.exp_power_of_2(6) * t6;
+        let t31 = t30.exp_power_of_2(1) * t1;
+        let t32 = t31.exp_power_of_2(1) * t1;
+
+        // self^((2^31 - 1) * 2^33 + 2^32 - 1) = self^(2^64 - 2^32 - 1)
+        t31.exp_power_of_2(33) * t32
+    }
+
+    // legendre computes the Legendre symbol self^((p-1)/2): 0 for zero,
+    // 1 for a non zero square and -1 for a non square
+    pub fn legendre(&self) -> i8 {
+        let symbol = self.pow((P - 1) / 2);
+        if symbol == FieldElement::new(0) {
+            0
+        } else if symbol == FieldElement::new(1) {
+            1
+        } else {
+            -1
+        }
+    }
+
+    // whether self has a square root, 0 included since its sqrt is 0
+    pub fn is_square(&self) -> bool {
+        self.legendre() >= 0
+    }
+
+    // sqrt computes a square root with the Tonelli-Shanks algorithm, using p - 1 = 2^32 * q
+    // returns None if self is not a square
+    pub fn sqrt(&self) -> Option<FieldElement> {
+        match self.legendre() {
+            0 => return Some(FieldElement::new(0)),
+            -1 => return None,
+            _ => {}
+        }
+
+        // 7 generates the multiplicative group, so it is not a square
+        let mut m = TWO_ADICITY;
+        let mut c = FieldElement::new(7).pow(ODD_FACTOR);
+        let mut t = self.pow(ODD_FACTOR);
+        let mut r = self.pow(ODD_FACTOR.div_ceil(2));
+
+        while t != FieldElement::new(1) {
+            // find the least i such that t^(2^i) = 1
+            let mut i = 0;
+            let mut t2i = t;
+            while t2i != FieldElement::new(1) {
+                t2i = t2i * t2i;
+                i += 1;
+            }
+
+            let b = c.exp_power_of_2((m - i - 1) as usize);
+            m = i;
+            c = b * b;
+            t *= c;
+            r *= b;
+        }
+        Some(r)
+    }
 
     // inv computes the multiplicative inverse of a FieldElement by using the extended Euclidean algorithm
     pub fn inv(&self) -> FieldElement {
@@ -156,10 +472,10 @@ impl FieldElement {
 
         //find s0 modulo P
         if s0 < 0 {
-            s0 = s0 + P as i128;
+            s0 += P as i128;
         }
 
-        return FieldElement::new(s0.try_into().unwrap());
+        FieldElement::new(s0.try_into().unwrap())
 
     }
 
@@ -191,12 +507,10 @@ impl FieldElement {
 
         
         for i in (1..values.len()).rev() {
-            print!("i: {}", i);
             let output = inv * partials[i-1];
-            print!("output: {}", output.value);
             // set the ith element of outputs to output
             outputs.push(output);
-            inv = inv * values[i];
+            inv *= values[i];
         }
 
         // set the first element of outputs to inv
@@ -220,6 +534,8 @@ impl FieldElement {
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use rand_chacha::ChaCha8Rng;
+    use rand_core::SeedableRng;
 
     #[test]
     fn test_generator() {
@@ -279,14 +595,14 @@ mod tests {
     #[test]
     fn test_pow() {
         let a = FieldElement::new(2);
-        let b = 3 as u64;
+        let b = 3_u64;
         assert_eq!(a.pow(b), FieldElement::new(8));
     }
 
     #[test]
     fn test_pow_2() {
         let a = FieldElement::new(P-3);
-        let b = 3 as u64;
+        let b = 3_u64;
         assert_eq!(a.pow(b), a*a*a);
     }
 
@@ -326,6 +642,185 @@ mod tests {
         assert_eq!(a*b, FieldElement::new(2));
     }
 
+    #[test]
+    fn test_mul_reduction() {
+        let values = [0, 1, 2, EPSILON, EPSILON + 1, P - 1, P - 2, 0x185629dcda58878c, 0xfffffffe00000003];
+        for a in values {
+            for b in values {
+                let expected = ((a as u128 * b as u128) % P as u128) as u64;
+                assert_eq!(FieldElement::new(a)*FieldElement::new(b), FieldElement::new(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn test_pow_ct() {
+        let a = FieldElement::new(P-3);
+        for exp in [0, 1, 2, 3, 42, P-2, P-1, u64::MAX] {
+            assert_eq!(a.pow_ct(exp), a.pow(exp));
+        }
+    }
+
+    #[test]
+    fn test_inv_ct() {
+        for a in [1, 2, 42, P-2, P-1, 0x185629dcda58878c] {
+            let a = FieldElement::new(a);
+            assert_eq!(a.inv_ct(), a.inv());
+            assert_eq!(a*a.inv_ct(), FieldElement::new(1));
+        }
+        assert_eq!(FieldElement::new(0).inv_ct(), FieldElement::new(0));
+    }
+
+    #[test]
+    fn test_legendre() {
+        assert_eq!(FieldElement::new(0).legendre(), 0);
+        assert_eq!(FieldElement::new(4).legendre(), 1);
+        assert_eq!(FieldElement::new(7).legendre(), -1);
+        assert!(FieldElement::new(9).is_square());
+        assert!(!FieldElement::new(7).is_square());
+        // 0 counts as a square, like for sqrt
+        assert!(FieldElement::new(0).is_square());
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(FieldElement::new(0).sqrt(), Some(FieldElement::new(0)));
+        assert_eq!(FieldElement::new(7).sqrt(), None);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(42);
+        for _ in 0..32 {
+            let a = FieldElement::random(&mut rng);
+            let square = a * a;
+            let root = square.sqrt().unwrap();
+            assert_eq!(root * root, square);
+            assert!(root == a || root == -a);
+        }
+    }
+
+    #[test]
+    fn test_sqrt_two_adic() {
+        // roots of unity of high 2-adic order need several Tonelli-Shanks iterations
+        let base:u64 = 2;
+        let a = FieldElement::nth_root_of_unity(base.pow(31));
+        let root = a.sqrt().unwrap();
+        assert_eq!(root * root, a);
+    }
+
+    #[test]
+    fn test_random() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let a = FieldElement::random(&mut rng);
+        let b = FieldElement::random(&mut rng);
+        assert_ne!(a, b);
+        assert!(a.value < P && b.value < P);
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let a = FieldElement::new(P-1);
+        assert_eq!(FieldElement::from_bytes(&a.to_bytes()), Ok(a));
+        assert_eq!(FieldElement::from_bytes(&P.to_be_bytes()), Err(DecodeError::NonCanonical(P)));
+        assert_eq!(FieldElement::from_bytes(&u64::MAX.to_be_bytes()), Err(DecodeError::NonCanonical(u64::MAX)));
+        assert_eq!(FieldElement::from_bytes(&[1, 2, 3]), Err(DecodeError::InvalidLength(3)));
+        assert_eq!(FieldElement::from_bytes(&[0; 9]), Err(DecodeError::InvalidLength(9)));
+    }
+
+    #[test]
+    fn test_batch_bytes() {
+        let values = [FieldElement::new(0), FieldElement::new(42), FieldElement::new(P-1)];
+        let bytes = FieldElement::batch_to_bytes(&values);
+        assert_eq!(FieldElement::batch_from_bytes(&bytes), Ok(values.to_vec()));
+
+        let mut bad = bytes.clone();
+        bad[1] = vec![0xff; 8];
+        assert!(FieldElement::batch_from_bytes(&bad).is_err());
+    }
+
+    #[test]
+    fn test_assign_ops() {
+        let a = FieldElement::new(5);
+        let b = FieldElement::new(3);
+        let mut acc = FieldElement::default();
+        acc += a * b;
+        assert_eq!(acc, FieldElement::new(15));
+        acc -= &b;
+        assert_eq!(acc, FieldElement::new(12));
+        acc *= b;
+        assert_eq!(acc, FieldElement::new(36));
+        acc /= &b;
+        assert_eq!(acc, FieldElement::new(12));
+    }
+
+    #[test]
+    fn test_ref_ops() {
+        let a = FieldElement::new(P-1);
+        let b = FieldElement::new(2);
+        // each reference and assign variant agrees with the by value operator
+        macro_rules! check {
+            ($op:tt, $op_assign:tt) => {
+                let expected = a $op b;
+                assert_eq!(&a $op &b, expected);
+                assert_eq!(a $op &b, expected);
+                assert_eq!(&a $op b, expected);
+                let mut c = a;
+                c $op_assign b;
+                assert_eq!(c, expected);
+                let mut c = a;
+                c $op_assign &b;
+                assert_eq!(c, expected);
+            };
+        }
+        check!(+, +=);
+        check!(-, -=);
+        check!(*, *=);
+        check!(/, /=);
+        assert_eq!(a + b, FieldElement::new(1));
+        assert_eq!(a - b, FieldElement::new(P-3));
+        assert_eq!(a * b, FieldElement::new(P-2));
+        assert_eq!((a / b) * b, a);
+        assert_eq!(-&a, -a);
+    }
+
+    #[test]
+    fn test_sum_product() {
+        let values = [FieldElement::new(P-1), FieldElement::new(2), FieldElement::new(3)];
+        assert_eq!(values.iter().sum::<FieldElement>(), FieldElement::new(4));
+        assert_eq!(values.into_iter().product::<FieldElement>(), FieldElement::new(P-6));
+        assert_eq!(std::iter::empty::<FieldElement>().sum::<FieldElement>(), FieldElement::new(0));
+        assert_eq!(std::iter::empty::<FieldElement>().product::<FieldElement>(), FieldElement::new(1));
+    }
+
+    #[test]
+    fn test_conversions_and_display() {
+        assert_eq!(FieldElement::from(P+1), FieldElement::new(1));
+        assert_eq!(FieldElement::from(7_u32), FieldElement::new(7));
+        assert_eq!(FieldElement::from(true), FieldElement::new(1));
+        assert_eq!(FieldElement::from(false), FieldElement::new(0));
+        assert_eq!(FieldElement::new(P-1).to_string(), "18446744069414584320");
+        assert!(FieldElement::new(1) < FieldElement::new(P-1));
+    }
+
+    #[test]
+    fn test_hash_map_key() {
+        use std::collections::HashMap;
+        let mut table = HashMap::new();
+        table.insert(FieldElement::new(42), 1);
+        table.insert(FieldElement::new(P+42), 2);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table[&FieldElement::new(42)], 2);
+    }
+
+    #[test]
+    fn test_ct_eq() {
+        let a = FieldElement::new(42);
+        let b = FieldElement::new(P+42);
+        let c = FieldElement::new(43);
+        assert!(bool::from(a.ct_eq(&b)));
+        assert!(!bool::from(a.ct_eq(&c)));
+        assert_eq!(FieldElement::conditional_select(&a, &c, Choice::from(1)), c);
+        assert_eq!(FieldElement::conditional_select(&a, &c, Choice::from(0)), a);
+    }
+
 }
 
 
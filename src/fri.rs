@@ -1,10 +1,14 @@
 // FRI commitment scheme implementation
 
-use crate::field::{FieldElement, P};
+use crate::domain::Domain;
+use crate::field::{FieldElement, TWO_ADICITY};
 use crate::merkle_tree::{merkelize,mk_branch, verify_branch};
 use crate::poly::Poly;
 use crate::utils::{get_pseudorandom_indices};
-use core::cmp::min;
+use crate::lagrange::LagrangePoly;
+
+// positions of the column opened on each layer, each with the 4 positions of the layer above
+pub const NUM_QUERIES: usize = 40;
 
 #[derive(Debug)]
 pub struct FRIComponent {
@@ -22,11 +26,6 @@ pub fn prove_low_degree(
     avoid_multiples : u64
 ) -> Vec<FRIComponent> {
 
-    println!("Starting FRI proof generation");
-    // println variables
-    println!("maxdeg_plus_1: {}", maxdeg_plus_1);
-    println!("values len: {}", values.len());
-    
     let mut fri_component = FRIComponent {
         root: vec![],
         values: vec![],
@@ -36,22 +35,19 @@ pub fn prove_low_degree(
 
     // If the degree is small enough, just return the values
     if maxdeg_plus_1 <= 16 {
-        fri_component.values = values.iter().map(|x| x.to_bytes().to_vec()).collect::<Vec<_>>();
+        fri_component.values = FieldElement::batch_to_bytes(&values);
         return vec![fri_component];
         //return values.iter().map(|x| x.to_bytes().to_vec()).collect::<Vec<_>>();
     }
 
-    let xs =FieldElement::get_power_cycle(root_of_unity);
+    let xs = Domain::power_cycle(root_of_unity);
     assert_eq!(values.len(), xs.len());
 
     // Compute the Merkle root of the values
-    let m = merkelize(&values.iter().map(|x| x.to_bytes().to_vec()).collect::<Vec<_>>());
+    let m = merkelize(&FieldElement::batch_to_bytes(&values));
 
     let quarter_len = xs.len() / 4;
 
-    println!("Done computing Merkle root");
-    println!("m[1]: {:?}", m[1]);
-
     // construct rows
     let mut x_polys = vec![];
 
@@ -65,7 +61,6 @@ pub fn prove_low_degree(
         x_polys.push(Poly::lagrange_interpolation(&xs_poly, &ys_poly));
     }
 
-    println!("Done constructing rows");
     // get a random x value
     let mut rng_merkle: [u8; 8] = [0; 8];
     rng_merkle.copy_from_slice(&merkle_root[0..8]);
@@ -75,14 +70,10 @@ pub fn prove_low_degree(
     // construct column by successive evaluations of rows at special_x
     let column = x_polys.iter().map(|p| p.eval(special_x)).collect::<Vec<_>>();
 
-    println!("Done constructing column");
-
     // Compute the Merkle root of the column
-    let m2 = merkelize(&column.iter().map(|x| x.to_bytes().to_vec()).collect::<Vec<_>>());
-
-    let ys = get_pseudorandom_indices(&m2[1], column.len() as u64, 40, avoid_multiples);
+    let m2 = merkelize(&FieldElement::batch_to_bytes(&column));
 
-    println!("Done computing pseudorandom indices");
+    let ys = get_pseudorandom_indices(&m2[1], column.len() as u64, NUM_QUERIES, avoid_multiples);
 
     // Compute the positions for the values in the polynomial
     // values are at positions y, y + n/4, y + 2n/4, y + 3n/4, y in ys and n = length of values
@@ -92,8 +83,6 @@ pub fn prove_low_degree(
 
     let positions_branches = poly_positions.iter().map(|&y| mk_branch(&m, y as usize).clone()).collect::<Vec<Vec<_>>>();
 
-    println!("Done computing branches");
-
     // juxtapose elements of the proof
     //let mut proof = vec![];
      
@@ -118,45 +107,87 @@ pub fn prove_low_degree(
 }
 
 pub fn get_branch_value(
-    branch: &Vec<Vec<u8>>, 
+    branch: &[Vec<u8>], 
 ) -> Vec<u8> {
-    let value = branch[0].clone();
-    value
+    branch[0].clone()
+}
+
+// a proof ends with the layer sent directly, and every layer before it opens NUM_QUERIES
+// positions of its column, each with the 4 positions of the layer above
+pub fn is_well_formed(proof: &[FRIComponent]) -> bool {
+    match proof.split_last() {
+        Some((_, layers)) => layers.iter().all(|c| {
+            c.ys_branches.len() == NUM_QUERIES && c.positions_branches.len() == 4 * NUM_QUERIES
+        }),
+        None => false,
+    }
+}
+
+// positions of the first layer opened by the proof, with the opened values
+// these are the points where the verifier checks the first layer against the polynomials it was
+// built from; when the first layer is sent directly, every position is opened
+pub fn first_layer_queries(
+    proof: &[FRIComponent],
+    domain_size: usize,
+    exclude_multiples_of: u64
+) -> Vec<(usize, Vec<u8>)> {
+    let first = &proof[0];
+    if proof.len() == 1 {
+        return first.values.iter().cloned().enumerate().collect();
+    }
+
+    let quarter_len = domain_size / 4;
+    let ys = get_pseudorandom_indices(&first.root, quarter_len as u64, NUM_QUERIES, exclude_multiples_of);
+    let positions = ys.iter().flat_map(|&y| (0..4).map(move |j| y as usize + quarter_len * j));
+    positions.zip(first.positions_branches.iter().map(|b| get_branch_value(b))).collect()
 }
 
  
 pub fn verify_low_degree_proof(
-    merkle_root: &Vec<u8>, 
+    merkle_root: &[u8], 
     root_of_unity: &FieldElement, 
-    proof: &Vec<FRIComponent>, 
+    proof: &[FRIComponent], 
     maxdeg_plus_1: usize, 
     exclude_multiples_of: u64
 ) -> bool {
 
-    let mut root1 = merkle_root.clone();
+    if !is_well_formed(proof) || merkle_root.len() < 8 {
+        return false;
+    }
+    let mut root1 = merkle_root.to_vec();
 
     //let modulus =FieldElement::new(P);
 
     // test 
-    let mut testval = root_of_unity.clone();
+    let mut testval = *root_of_unity;
     let mut deg_root = 1;
     while testval != FieldElement::new(1) {
+        // not a root of unity of power of two order
+        if deg_root >= 1u64 << TWO_ADICITY {
+            return false;
+        }
         deg_root *= 2;
         testval = testval * testval;
     }
 
-    let mut root_of_unity = root_of_unity.clone();
-    let mut maxdeg = maxdeg_plus_1.clone();
+    let mut root_of_unity = *root_of_unity;
 
-    let quartic_roots_of_unity = vec![
+    let quartic_roots_of_unity = [
         FieldElement::new(1),
         root_of_unity.pow(deg_root / 4),
         root_of_unity.pow(deg_root / 2),
         root_of_unity.pow(deg_root * 3 / 4)
     ];
 
+    // like prove_low_degree, fold the degree bound by 4 on each layer while it is > 16
+    let mut maxdeg_plus_1 = maxdeg_plus_1;
+
     for prf_component in proof.iter().take(proof.len() - 1) {
-        let mut root2 = prf_component.root.clone();
+        // more layers than the domain can be folded into, or than the degree bound needs
+        if deg_root < 4 || maxdeg_plus_1 <= 16 {
+            return false;
+        }
+        let root2 = prf_component.root.clone();
 
         // get special x, a pseudorandom value deciding the column we're going to check
 
@@ -168,7 +199,7 @@ pub fn verify_low_degree_proof(
         let ys = get_pseudorandom_indices(
             &root2,
             deg_root / 4,
-            40,
+            NUM_QUERIES,
             exclude_multiples_of
         );
 
@@ -183,36 +214,38 @@ pub fn verify_low_degree_proof(
         }
 
         // Verify Merkle branches for columns and poly positions
-        for i in 0..poly_positions.len() {
+        for (i, position) in poly_positions.iter().enumerate() {
                 //column check
-            if !verify_branch(&root1, poly_positions[i] as usize, &prf_component.positions_branches[i]) {
+            if !verify_branch(&root1, *position as usize, &prf_component.positions_branches[i]) {
                 return false;
             }
         }
-        let poly_values = prf_component.positions_branches.iter().map(|y| get_branch_value(&y)).collect::<Vec<_>>();
+        let poly_values = prf_component.positions_branches.iter().map(|y| get_branch_value(y)).collect::<Vec<_>>();
 
-        for i in 0..ys.len() {
-            if !verify_branch(&root2, ys[i] as usize, &prf_component.ys_branches[i]) {
+        for (i, y) in ys.iter().enumerate() {
+            if !verify_branch(&root2, *y as usize, &prf_component.ys_branches[i]) {
                 return false;
             }
         }
 
-        let column_values = prf_component.ys_branches.iter().map(|y| get_branch_value(&y)).collect::<Vec<_>>();
+        let column_values = prf_component.ys_branches.iter().map(|y| get_branch_value(y)).collect::<Vec<_>>();
 
         for i in 0..ys.len(){
             //get x coordinates
             // we have 4 x coordinates for each y coordinate
             let y = ys[i];
             let x1 = root_of_unity.pow(y);
-            let xcoord = (0..4).map(|j| (quartic_roots_of_unity[j] * x1)).collect::<Vec<_>>();
+            let xcoord = (0..4).map(|j| quartic_roots_of_unity[j] * x1).collect::<Vec<_>>();
            
-            let row = poly_values[i * 4..(i + 1) * 4]
-                .iter()
-                .map(|x| FieldElement::new(u64::from_be_bytes(x[..8].try_into().unwrap())))
-                .collect::<Vec<_>>();
+            let row = match FieldElement::batch_from_bytes(&poly_values[i * 4..(i + 1) * 4]) {
+                Ok(row) => row,
+                Err(_) => return false,
+            };
            
-            //TODO : create a field element from bytes function
-            let columnval = FieldElement::new(u64::from_be_bytes(column_values[i][..8].try_into().unwrap()));
+            let columnval = match FieldElement::from_bytes(&column_values[i]) {
+                Ok(columnval) => columnval,
+                Err(_) => return false,
+            };
             
             // do lagrange interpolation
             let poly = Poly::lagrange_interpolation(&xcoord, &row);
@@ -227,46 +260,44 @@ pub fn verify_low_degree_proof(
 
         // update root1 and root_of_unity
         root1 = root2.clone();
-        root_of_unity = root_of_unity.pow(4).clone();
-        maxdeg /= 4;
+        root_of_unity = root_of_unity.pow(4);
         deg_root /= 4;
+        maxdeg_plus_1 /= 4;
     
     }
     
     // Verify the direct components of the proof
     let values = proof.last().unwrap().values.clone();
-    println!("Verifying degree <= {}", maxdeg_plus_1);
-    assert!(maxdeg_plus_1 <= 16);
+    if maxdeg_plus_1 > 16 {
+        return false;
+    }
     
     // Check the Merkle root matches up
     let mtree = merkelize(&values);
-    assert_eq!(mtree[1], root1);
+    if mtree[1] != root1 {
+        return false;
+    }
     
     // Check the degree of the data
     //TODO : exclude_multiples_of, avoid multiples of the generator
     
-    let powers = FieldElement::get_power_cycle(root_of_unity);
-    let values_full = values.iter().map(|x| FieldElement::new(u64::from_be_bytes(x[..8].try_into().unwrap()))).collect::<Vec<_>>();
-    let max_length = min(maxdeg_plus_1, values_full.len());
-    let values_short = values_full[..max_length].to_vec();
-
-    let poly_short = Poly::lagrange_interpolation(
-        &powers[..max_length],
-        &values_short,
-    );
-
-    let poly_full = Poly::lagrange_interpolation(
-        &powers,
-        &values_full,
-    );
+    let values_full = match FieldElement::batch_from_bytes(&values) {
+        Ok(values_full) => values_full,
+        Err(_) => return false,
+    };
 
-    for i in 0..values.len() {
-        // if both are equal, poly_full is defined by maxdeg_plus_1 points
-        assert_eq!(poly_full.eval(powers[i]), poly_short.eval(powers[i]));
+    // the values are the remainder polynomial in Lagrange form over the powers of the root of unity
+    if !values_full.len().is_power_of_two() {
+        return false;
     }
-    
-    
-    println!("FRI proof verified");
+    let remainder = LagrangePoly::on_subgroup(values_full);
+    if remainder.generator() != root_of_unity {
+        return false;
+    }
+    if remainder.to_poly().degree() >= maxdeg_plus_1 {
+        return false;
+    }
+
     true
 }    
 
@@ -314,12 +345,12 @@ mod tests {
             FieldElement::new(8),
         ];
 
-        let root_of_unity: FieldElement = FieldElement::new(7);
+        let root_of_unity: FieldElement = FieldElement::nth_root_of_unity(32);
 
         //map the values to bytes
         //let values: Vec<[u8; 8]> = values.iter().map(|x| x.to_bytes()).collect();
 
-        let merkle = merkelize(&values.iter().map(|x| x.to_bytes().to_vec()).collect::<Vec<_>>());
+        let merkle = merkelize(&FieldElement::batch_to_bytes(&values));
 
         println!("Began proving");
 
@@ -328,7 +359,59 @@ mod tests {
         println!("proof: {:?}", proof);
 
         //assert!(false)
-        assert!(verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 12, 7));
+        assert!(verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 32, 7));
 
     }
+
+    #[test]
+    fn test_malformed_layers() {
+        let values = (0..64).map(|i| FieldElement::new(i * i)).collect::<Vec<_>>();
+        let root_of_unity = FieldElement::nth_root_of_unity(64);
+        let merkle = merkelize(&FieldElement::batch_to_bytes(&values));
+        let proof = prove_low_degree(values, root_of_unity, merkle[1].clone(), 64, 0);
+        assert!(is_well_formed(&proof));
+        assert!(!is_well_formed(&[]));
+        assert!(!verify_low_degree_proof(&merkle[1], &root_of_unity, &[], 64, 0));
+
+        // a layer missing some of its branches
+        let mut proof = proof;
+        proof[0].ys_branches.pop();
+        assert!(!is_well_formed(&proof));
+        assert!(!verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 64, 0));
+        proof[0].positions_branches.truncate(10);
+        assert!(!verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 64, 0));
+    }
+
+    #[test]
+    fn test_malformed_direct_proof() {
+        // a direct proof whose values aren't over a power of two subgroup, or for a degree bound
+        // that should have been folded, is rejected rather than panicking
+        let values = (1..13).map(FieldElement::new).collect::<Vec<_>>();
+        let merkle = merkelize(&FieldElement::batch_to_bytes(&values));
+        let proof = prove_low_degree(values, FieldElement::nth_root_of_unity(16), merkle[1].clone(), 12, 1);
+        assert!(!verify_low_degree_proof(&merkle[1], &FieldElement::nth_root_of_unity(16), &proof, 12, 1));
+        assert!(!verify_low_degree_proof(&merkle[1], &FieldElement::nth_root_of_unity(12), &proof, 12, 1));
+
+        let values = (1..17).map(FieldElement::new).collect::<Vec<_>>();
+        let merkle = merkelize(&FieldElement::batch_to_bytes(&values));
+        let proof = prove_low_degree(values, FieldElement::nth_root_of_unity(16), merkle[1].clone(), 16, 1);
+        assert!(!verify_low_degree_proof(&merkle[1], &FieldElement::nth_root_of_unity(16), &proof, 64, 1));
+    }
+
+    #[test]
+    fn test_extra_fold_layer() {
+        // a proof folded once more than the degree bound needs would check the last layer against
+        // a bound 4 times too large
+        let values = (0..64).map(|i| FieldElement::new(i * i)).collect::<Vec<_>>();
+        let root_of_unity = FieldElement::nth_root_of_unity(64);
+        let merkle = merkelize(&FieldElement::batch_to_bytes(&values));
+        let proof = prove_low_degree(values.clone(), root_of_unity, merkle[1].clone(), 64, 0);
+        assert_eq!(proof.len(), 2);
+        assert!(verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 64, 0));
+        assert!(!verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 16, 0));
+
+        let proof = prove_low_degree(values, root_of_unity, merkle[1].clone(), 256, 0);
+        assert_eq!(proof.len(), 3);
+        assert!(!verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 64, 0));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,127 @@
+// polynomials in Lagrange form, i.e. given by their evaluations over a multiplicative
+// subgroup <g> of size n or one of its cosets offset * <g>
+
+use crate::domain::Domain;
+use crate::field::FieldElement;
+use crate::poly::Poly;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LagrangePoly {
+    // evals[i] is the evaluation at offset * g^i
+    pub evals: Vec<FieldElement>,
+    pub offset: FieldElement,
+}
+
+impl LagrangePoly {
+    pub fn new(evals: Vec<FieldElement>, offset: FieldElement) -> LagrangePoly {
+        assert!(evals.len().is_power_of_two(), "the domain size must be a power of 2");
+        assert!(offset != FieldElement::new(0), "the coset offset must be non zero");
+        LagrangePoly { evals, offset }
+    }
+
+    // evaluations over the subgroup itself
+    pub fn on_subgroup(evals: Vec<FieldElement>) -> LagrangePoly {
+        LagrangePoly::new(evals, FieldElement::new(1))
+    }
+
+    // evaluate p on the coset of size n, p must have degree < n
+    pub fn from_poly(p: &Poly, n: usize, offset: FieldElement) -> LagrangePoly {
+        let root = Domain::root_of_unity(n.trailing_zeros());
+        LagrangePoly::new(Poly::coset_fft(&p.coeffs, offset, &root), offset)
+    }
+
+    // back to coefficient form
+    pub fn to_poly(&self) -> Poly {
+        Poly::new(Poly::coset_inv_fft(&self.evals, self.offset, &self.generator()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.evals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.evals.is_empty()
+    }
+
+    // generator of the subgroup
+    pub fn generator(&self) -> FieldElement {
+        Domain::root_of_unity(self.len().trailing_zeros())
+    }
+
+    // barycentric evaluation at an arbitrary point in O(n), without interpolating
+    // with x_i = offset * g^i the vanishing polynomial of the coset is Z(x) = x^n - offset^n
+    // and Z'(x_i) = n * offset^n / x_i, so
+    // p(z) = Z(z) / (n * offset^n) * sum y_i * x_i / (z - x_i)
+    pub fn eval(&self, z: FieldElement) -> FieldElement {
+        let n = self.len();
+        let domain = Domain::new(n.trailing_zeros());
+        let xs = domain.powers().iter().map(|g| self.offset * *g).collect::<Vec<_>>();
+
+        let differences = xs.iter().map(|x| z - *x).collect::<Vec<_>>();
+        // z is in the domain, no need to interpolate
+        if let Some(i) = differences.iter().position(|d| *d == FieldElement::new(0)) {
+            return self.evals[i];
+        }
+
+        let inv_differences = FieldElement::multi_inv(&differences);
+        let sum: FieldElement = self.evals.iter()
+            .zip(xs.iter())
+            .zip(inv_differences.iter())
+            .map(|((y, x), inv)| *y * *x * *inv)
+            .sum();
+
+        let offset_n = self.offset.pow(n as u64);
+        let z_n = z.pow(n as u64) - offset_n;
+        z_n * (FieldElement::new(n as u64) * offset_n).inv() * sum
+    }
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_poly() -> Poly {
+        Poly::new((1..=6).map(|i| FieldElement::new(i * 31 + 2)).collect())
+    }
+
+    #[test]
+    fn test_eval_subgroup() {
+        let p = sample_poly();
+        let l = LagrangePoly::from_poly(&p, 8, FieldElement::new(1));
+        for z in [0, 1, 5, 123456789] {
+            let z = FieldElement::new(z);
+            assert_eq!(l.eval(z), p.eval(z));
+        }
+        // points of the domain are looked up directly
+        let g = l.generator();
+        assert_eq!(l.eval(g.pow(3)), l.evals[3]);
+    }
+
+    #[test]
+    fn test_eval_coset() {
+        let p = sample_poly();
+        let offset = FieldElement::generator();
+        let l = LagrangePoly::from_poly(&p, 16, offset);
+        for z in [0, 2, 99, 0xffffffff] {
+            let z = FieldElement::new(z);
+            assert_eq!(l.eval(z), p.eval(z));
+        }
+        assert_eq!(l.eval(offset * l.generator()), l.evals[1]);
+    }
+
+    #[test]
+    fn test_to_poly() {
+        let p = sample_poly();
+        let offset = FieldElement::generator();
+        assert_eq!(LagrangePoly::from_poly(&p, 8, offset).to_poly(), p);
+
+        let evals = (0..4).map(FieldElement::new).collect::<Vec<_>>();
+        let l = LagrangePoly::on_subgroup(evals.clone());
+        let q = l.to_poly();
+        let g = l.generator();
+        for (i, y) in evals.iter().enumerate() {
+            assert_eq!(q.eval(g.pow(i as u64)), *y);
+        }
+    }
+}
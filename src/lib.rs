@@ -2,8 +2,20 @@
 pub mod field;
 pub use field::*;
 
+pub mod domain;
+pub mod ntt;
+pub mod lagrange;
 pub mod poly;
+pub mod subproduct_tree;
+pub mod assertion;
+pub mod expression;
+pub mod air;
 pub mod prover;
 pub mod merkle_tree;
 pub mod fri;
 pub mod utils;
+pub mod transcript;
+pub mod verifier;
+pub mod multiset;
+pub mod lookup;
+pub mod mimc;
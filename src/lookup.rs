@@ -0,0 +1,400 @@
+// LogUp lookup argument: the values of some trace columns all appear in a table column
+// https://eprint.iacr.org/2022/1530
+// with a column m counting how many times each row of the table t is looked up, the values
+// f_c[i] are all in the table exactly when, as rational functions,
+//   sum_i sum_c 1 / (X - f_c[i]) = sum_i m_i / (X - t_i)
+// which is checked at a random alpha drawn after the commitment of the main trace, with the
+// auxiliary running sum S_0 = 0, S_(i+1) = S_i + sum_c 1 / (alpha - f_c[i]) - m_i / (alpha - t_i)
+// whose transition holds on every step, the last one wrapping around to S_0
+// LookupAir adds the multiplicity columns to the main trace of an air, the running sums to its
+// auxiliary trace, and their constraints to its own
+
+use std::collections::HashMap;
+
+use crate::air::{Air, PeriodicColumn, TransitionDomain};
+use crate::assertion::Assertion;
+use crate::expression::Expr;
+use crate::field::FieldElement;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LookupTable {
+    // a main trace column, committed by the prover
+    Column(usize),
+    // a periodic column, fixed by the air, e.g. 0..2^k for range checks
+    Periodic(usize),
+}
+
+// the values of the main trace columns are looked up in the table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lookup {
+    pub columns: Vec<usize>,
+    pub table: LookupTable,
+}
+
+impl Lookup {
+    pub fn new(columns: Vec<usize>, table: LookupTable) -> Lookup {
+        assert!(!columns.is_empty(), "a lookup needs at least one column");
+        Lookup { columns, table }
+    }
+
+    // degree of the transition, the running sum times the denominators of the columns and the table
+    pub fn degree(&self) -> usize {
+        self.columns.len() + 2
+    }
+}
+
+// an air with lookups, whose trace is laid out as
+// [main columns of the air | multiplicities | auxiliary columns of the air | running sums]
+// with one multiplicity and one running sum column per lookup, and one more challenge alpha
+pub struct LookupAir<A: Air> {
+    air: A,
+    lookups: Vec<Lookup>,
+}
+
+impl<A: Air> LookupAir<A> {
+    pub fn new(air: A, lookups: Vec<Lookup>) -> LookupAir<A> {
+        for lookup in lookups.iter() {
+            assert!(lookup.columns.iter().all(|c| *c < air.num_columns()), "lookups must be on main trace columns");
+            match lookup.table {
+                LookupTable::Column(c) => assert!(c < air.num_columns(), "the table must be a main trace column"),
+                LookupTable::Periodic(p) => assert!(p < air.periodic_columns().len(), "no such periodic column"),
+            }
+        }
+        LookupAir { air, lookups }
+    }
+
+    pub fn inner(&self) -> &A {
+        &self.air
+    }
+
+    fn multiplicity_column(&self, l: usize) -> usize {
+        self.air.num_columns() + l
+    }
+
+    fn running_sum_column(&self, l: usize) -> usize {
+        self.air.num_columns() + self.lookups.len() + self.air.num_aux_columns() + l
+    }
+
+    // the table at every step
+    fn table_values(&self, lookup: &Lookup, main: &[Vec<FieldElement>]) -> Vec<FieldElement> {
+        match lookup.table {
+            LookupTable::Column(c) => main[c].clone(),
+            LookupTable::Periodic(p) => {
+                let column = &self.air.periodic_columns()[p];
+                (0..self.air.steps()).map(|i| column.values()[i % column.period()]).collect()
+            }
+        }
+    }
+
+    // the number of times each row of the table is looked up, counted at the first row holding
+    // each value
+    // values missing from the table aren't counted, and the proof won't verify
+    pub fn multiplicities(&self, lookup: &Lookup, main: &[Vec<FieldElement>]) -> Vec<FieldElement> {
+        let table = self.table_values(lookup, main);
+        let mut rows = HashMap::new();
+        for (i, t) in table.iter().enumerate().rev() {
+            rows.insert(*t, i);
+        }
+        let mut counts = vec![0u64; table.len()];
+        for c in lookup.columns.iter() {
+            for f in main[*c].iter() {
+                if let Some(i) = rows.get(f) {
+                    counts[*i] += 1;
+                }
+            }
+        }
+        counts.into_iter().map(FieldElement::new).collect()
+    }
+
+    // the main trace of the air followed by the multiplicity column of each lookup
+    pub fn main_trace(&self, trace: &[Vec<FieldElement>]) -> Vec<Vec<FieldElement>> {
+        let mut main = trace.to_vec();
+        for lookup in self.lookups.iter() {
+            main.push(self.multiplicities(lookup, trace));
+        }
+        main
+    }
+
+    // S_0 = 0, S_(i+1) = S_i + sum_c 1 / (alpha - f_c[i]) - m_i / (alpha - t_i)
+    fn running_sum(&self, l: usize, main: &[Vec<FieldElement>], alpha: FieldElement) -> Vec<FieldElement> {
+        let lookup = &self.lookups[l];
+        let table = self.table_values(lookup, main);
+        let multiplicities = &main[self.multiplicity_column(l)];
+
+        // one batch inversion for the denominators of every row, the table last on each row
+        let width = lookup.columns.len() + 1;
+        let denominators = (0..self.air.steps())
+            .flat_map(|i| {
+                lookup.columns.iter()
+                    .map(move |c| alpha - main[*c][i])
+                    .chain(std::iter::once(alpha - table[i]))
+            })
+            .collect::<Vec<_>>();
+        let inverses = FieldElement::multi_inv(&denominators);
+
+        let mut sum = vec![FieldElement::new(0)];
+        for i in 0..self.air.steps() - 1 {
+            let row = &inverses[i * width..(i + 1) * width];
+            let looked_up: FieldElement = row[..width - 1].iter().sum();
+            sum.push(sum[i] + looked_up - multiplicities[i] * row[width - 1]);
+        }
+        sum
+    }
+
+    // the transition of a lookup as an expression, like in evaluate_transition
+    fn lookup_constraint(&self, l: usize) -> Expr {
+        let lookup = &self.lookups[l];
+        let alpha = Expr::challenge(self.air.num_challenges());
+        let d = lookup.columns.iter().map(|c| alpha.clone() - Expr::current(*c)).collect::<Vec<_>>();
+        let d_t = alpha - match lookup.table {
+            LookupTable::Column(c) => Expr::current(c),
+            LookupTable::Periodic(p) => Expr::periodic(p),
+        };
+        let product = |skip: Option<usize>| {
+            d.iter().enumerate()
+                .filter(|(j, _)| Some(*j) != skip)
+                .map(|(_, d)| d.clone())
+                .reduce(|a, b| a * b)
+                .unwrap_or_else(|| FieldElement::new(1).into())
+        };
+        let looked_up = (0..d.len()).map(|c| product(Some(c))).reduce(|a, b| a + b).unwrap();
+        let s = self.running_sum_column(l);
+        (Expr::next(s) - Expr::current(s)) * d_t.clone() * product(None) - d_t * looked_up
+            + Expr::current(self.multiplicity_column(l)) * product(None)
+    }
+
+    // the row of the air alone, without the lookup columns
+    fn inner_row(&self, row: &[FieldElement]) -> Vec<FieldElement> {
+        let main = self.air.num_columns();
+        let aux = main + self.lookups.len();
+        [&row[..main], &row[aux..aux + self.air.num_aux_columns()]].concat()
+    }
+}
+
+impl<A: Air> Air for LookupAir<A> {
+    fn steps(&self) -> usize {
+        self.air.steps()
+    }
+
+    fn num_columns(&self) -> usize {
+        self.air.num_columns() + self.lookups.len()
+    }
+
+    // the expressions of the air, on its columns of the wider trace, then those of the lookups
+    // an air without expressions gives none, and is only evaluated through evaluate_transition
+    fn transition_constraints(&self) -> Vec<Expr> {
+        let inner = self.air.transition_constraints();
+        if inner.is_empty() {
+            return vec![];
+        }
+        let main = self.air.num_columns();
+        let mut constraints = inner.iter()
+            .map(|e| e.map_columns(&|c| if c < main { c } else { c + self.lookups.len() }))
+            .collect::<Vec<_>>();
+        constraints.extend((0..self.lookups.len()).map(|l| self.lookup_constraint(l)));
+        constraints
+    }
+
+    fn transition_degrees(&self) -> Vec<usize> {
+        let mut degrees = self.air.transition_degrees();
+        degrees.extend(self.lookups.iter().map(|l| l.degree()));
+        degrees
+    }
+
+    // the constraints of the air, then for each lookup, with d_c = alpha - f_c and d_t = alpha - t
+    // (S(g1 x) - S(x)) d_t prod d_c - d_t sum_c prod_(c' != c) d_c' + m prod d_c
+    fn evaluate_transition(
+        &self,
+        current: &[FieldElement],
+        next: &[FieldElement],
+        periodic: &[FieldElement],
+        challenges: &[FieldElement]
+    ) -> Vec<FieldElement> {
+        let inner_challenges = &challenges[..self.air.num_challenges()];
+        let mut o = self.air.evaluate_transition(&self.inner_row(current), &self.inner_row(next), periodic, inner_challenges);
+
+        let alpha = challenges[self.air.num_challenges()];
+        for (l, lookup) in self.lookups.iter().enumerate() {
+            let d = lookup.columns.iter().map(|c| alpha - current[*c]).collect::<Vec<_>>();
+            let d_t = alpha - match lookup.table {
+                LookupTable::Column(c) => current[c],
+                LookupTable::Periodic(p) => periodic[p],
+            };
+            let product: FieldElement = d.iter().product();
+            let looked_up: FieldElement = (0..d.len())
+                .map(|c| d.iter().enumerate().filter(|(j, _)| *j != c).map(|(_, d)| *d).product::<FieldElement>())
+                .sum();
+            let s = self.running_sum_column(l);
+            o.push((next[s] - current[s]) * d_t * product - d_t * looked_up + current[self.multiplicity_column(l)] * product);
+        }
+        o
+    }
+
+    fn transition_domains(&self) -> Vec<TransitionDomain> {
+        let mut domains = self.air.transition_domains();
+        domains.extend(self.lookups.iter().map(|_| TransitionDomain::All));
+        domains
+    }
+
+    // the assertions of the air, past the multiplicities for its auxiliary columns, and every
+    // running sum starts at 0
+    fn assertions(&self) -> Vec<Assertion> {
+        let main = self.air.num_columns();
+        let mut assertions = self.air.assertions().iter()
+            .map(|a| if a.column() < main { a.clone() } else { a.with_column(a.column() + self.lookups.len()) })
+            .collect::<Vec<_>>();
+        assertions.extend((0..self.lookups.len()).map(|l| Assertion::single(self.running_sum_column(l), 0, FieldElement::new(0))));
+        assertions
+    }
+
+    fn periodic_columns(&self) -> Vec<PeriodicColumn> {
+        self.air.periodic_columns()
+    }
+
+    fn num_aux_columns(&self) -> usize {
+        self.air.num_aux_columns() + self.lookups.len()
+    }
+
+    fn num_challenges(&self) -> usize {
+        self.air.num_challenges() + 1
+    }
+
+    fn build_aux_trace(&self, main: &[Vec<FieldElement>], challenges: &[FieldElement]) -> Vec<Vec<FieldElement>> {
+        let inner_challenges = &challenges[..self.air.num_challenges()];
+        let mut aux = self.air.build_aux_trace(&main[..self.air.num_columns()], inner_challenges);
+        let alpha = challenges[self.air.num_challenges()];
+        aux.extend((0..self.lookups.len()).map(|l| self.running_sum(l, main, alpha)));
+        aux
+    }
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::air::AirError;
+    use crate::prover::prove;
+    use crate::verifier::verify;
+
+    // two columns x and y = 15 - x, a committed table column, and a 4 bit range table
+    struct RangeAir {
+        steps: usize,
+    }
+
+    impl Air for RangeAir {
+        fn steps(&self) -> usize {
+            self.steps
+        }
+
+        fn num_columns(&self) -> usize {
+            3
+        }
+
+        fn transition_constraints(&self) -> Vec<Expr> {
+            vec![Expr::current(0) + Expr::current(1) - FieldElement::new(15).into()]
+        }
+
+        fn assertions(&self) -> Vec<Assertion> {
+            vec![]
+        }
+
+        fn periodic_columns(&self) -> Vec<PeriodicColumn> {
+            vec![PeriodicColumn::new((0..16).map(FieldElement::new).collect())]
+        }
+    }
+
+    // x and y, and a committed table holding the steps in reverse order
+    fn range_trace(steps: usize) -> Vec<Vec<FieldElement>> {
+        let x = (0..steps as u64).map(|i| FieldElement::new(i * 7 % 16)).collect::<Vec<_>>();
+        let y = x.iter().map(|x| FieldElement::new(15) - *x).collect();
+        let table = (0..steps as u64).rev().map(FieldElement::new).collect();
+        vec![x, y, table]
+    }
+
+    #[test]
+    fn test_multiplicities() {
+        let air = LookupAir::new(RangeAir { steps: 32 }, vec![Lookup::new(vec![0, 1], LookupTable::Periodic(0))]);
+        let main = air.main_trace(&range_trace(32));
+        assert_eq!(main.len(), 4);
+        // x and y go twice over 0..16, and the counts are at the first period of the table
+        assert!(main[3][..16].iter().all(|m| *m == FieldElement::new(4)));
+        assert!(main[3][16..].iter().all(|m| *m == FieldElement::new(0)));
+
+        // the running sum wraps around to 0
+        let alpha = FieldElement::new(1234567);
+        let aux = air.build_aux_trace(&main, &[alpha]);
+        assert_eq!(aux.len(), 1);
+        let last: FieldElement = aux[0][31] + (alpha - main[0][31]).inv() + (alpha - main[1][31]).inv() - main[3][31] / (alpha - FieldElement::new(15));
+        assert_eq!(last, FieldElement::new(0));
+    }
+
+    #[test]
+    fn test_range_check() {
+        let steps = 32;
+        let air = LookupAir::new(RangeAir { steps }, vec![Lookup::new(vec![0, 1], LookupTable::Periodic(0))]);
+        let proof = prove(&air, &air.main_trace(&range_trace(steps))).unwrap();
+        assert!(proof.aux_trace_root.is_some());
+        assert!(verify(&air, &proof));
+
+        // 16 is out of range, and 15 - 16 wraps around to P - 1
+        let mut trace = range_trace(steps);
+        trace[0][3] = FieldElement::new(16);
+        trace[1][3] = FieldElement::new(15) - FieldElement::new(16);
+        assert!(!verify(&air, &prove(&air, &air.main_trace(&trace)).unwrap()));
+    }
+
+    #[test]
+    fn test_committed_table() {
+        let steps = 32;
+        let lookups = vec![
+            Lookup::new(vec![0], LookupTable::Periodic(0)),
+            Lookup::new(vec![0, 1], LookupTable::Column(2)),
+        ];
+        let air = LookupAir::new(RangeAir { steps }, lookups.clone());
+        assert_eq!(air.num_columns(), 5);
+        assert_eq!(air.num_aux_columns(), 2);
+        let proof = prove(&air, &air.main_trace(&range_trace(steps))).unwrap();
+        assert!(verify(&air, &proof));
+
+        // the multiplicities are committed with the main trace, so a proof with wrong ones fails
+        let mut main = air.main_trace(&range_trace(steps));
+        main[4][20] += FieldElement::new(1);
+        assert!(!verify(&air, &prove(&air, &main).unwrap()));
+    }
+
+    #[test]
+    fn test_transition_constraints() {
+        let lookups = vec![
+            Lookup::new(vec![0], LookupTable::Periodic(0)),
+            Lookup::new(vec![0, 1], LookupTable::Column(2)),
+        ];
+        let air = LookupAir::new(RangeAir { steps: 32 }, lookups);
+        let constraints = air.transition_constraints();
+        assert_eq!(constraints.iter().map(|e| e.degree()).collect::<Vec<_>>(), air.transition_degrees());
+
+        // the expressions agree with evaluate_transition
+        let row = |k: u64| (0..7).map(|i| FieldElement::new(k * 31 + i * i + 1)).collect::<Vec<_>>();
+        let (current, next, periodic, challenges) = (row(1), row(2), row(3), row(4));
+        let evaluated = constraints.iter().map(|e| e.eval(&current, &next, &periodic, &challenges)).collect::<Vec<_>>();
+        assert_eq!(evaluated, air.evaluate_transition(&current, &next, &periodic, &challenges));
+    }
+
+    #[test]
+    fn test_understated_inner_degree() {
+        // the degrees of the wrapped air are checked against its expressions
+        struct Understated(RangeAir);
+        impl Air for Understated {
+            fn steps(&self) -> usize { self.0.steps() }
+            fn num_columns(&self) -> usize { self.0.num_columns() }
+            fn transition_constraints(&self) -> Vec<Expr> { self.0.transition_constraints() }
+            fn transition_degrees(&self) -> Vec<usize> { vec![0] }
+            fn assertions(&self) -> Vec<Assertion> { self.0.assertions() }
+            fn periodic_columns(&self) -> Vec<PeriodicColumn> { self.0.periodic_columns() }
+        }
+
+        let steps = 32;
+        let air = LookupAir::new(Understated(RangeAir { steps }), vec![Lookup::new(vec![0, 1], LookupTable::Periodic(0))]);
+        let main = air.main_trace(&range_trace(steps));
+        assert_eq!(prove(&air, &main).unwrap_err(), AirError::UnderstatedTransitionDegree(0));
+    }
+}
@@ -43,12 +43,12 @@ pub fn main() {
 
         println!("Began proving");
 
-        let merkle = merkelize(&values.iter().map(|x| x.to_bytes().to_vec()).collect::<Vec<_>>());
+        let merkle = merkelize(&FieldElement::batch_to_bytes(&values));
 
         let proof = prove_low_degree(values, root_of_unity, merkle[1].clone(), 32, 7);
 
         println!("proof: {:?}", proof);
 
-        assert!(verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 12, 7));
+        assert!(verify_low_degree_proof(&merkle[1], &root_of_unity, &proof, 32, 7));
 
 }
@@ -1,9 +1,6 @@
 // implement a merkle tree
 
 // imporr the hash function
-use std::hash::{Hash, Hasher};
-
-
 use sha2::{Digest, Sha256};
 
 pub fn hash_sha(x: Vec<u8>) -> Vec<u8> {
@@ -11,20 +8,17 @@ pub fn hash_sha(x: Vec<u8>) -> Vec<u8> {
 }
 
 // takes a list of leaves and returns a merkle tree of length 2*n
-pub fn merkelize(L: &Vec<Vec<u8>>) -> Vec<Vec<u8>> {
-    println!("begin merkelize");
-    println!("L: {:?}", L);
-    let mut nodes: Vec<Vec<u8>> = vec![vec![]; L.len() * 2];
-    nodes[L.len()..].clone_from_slice(&L);
-    for i in (1..L.len()).rev() {
+pub fn merkelize(l: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut nodes: Vec<Vec<u8>> = vec![vec![]; l.len() * 2];
+    nodes[l.len()..].clone_from_slice(l);
+    for i in (1..l.len()).rev() {
         //takes nodes at 2*I and 2*I+1 and hashes them together
-        println!("i: {}", i);
         nodes[i] = hash_sha(nodes[2 * i].iter().chain(nodes[2 * i + 1].iter()).cloned().collect::<Vec<u8>>());
     }
     nodes
 }
 
-pub fn mk_branch(tree: &Vec<Vec<u8>>, mut index: usize) -> Vec<Vec<u8>> {
+pub fn mk_branch(tree: &[Vec<u8>], mut index: usize) -> Vec<Vec<u8>> {
     index += tree.len() / 2;
     let mut o: Vec<Vec<u8>> = vec![tree[index].clone()];
     while index > 1 {
@@ -38,8 +32,11 @@ pub fn mk_branch(tree: &Vec<Vec<u8>>, mut index: usize) -> Vec<Vec<u8>> {
     o
 }
 
-pub fn verify_branch(root: &Vec<u8>, index: usize, branch: &Vec<Vec<u8>>) -> bool {
-    let mut o = branch.first().unwrap().clone();
+pub fn verify_branch(root: &[u8], index: usize, branch: &[Vec<u8>]) -> bool {
+    let mut o = match branch.first() {
+        Some(leaf) => leaf.clone(),
+        None => return false,
+    };
     for (i, b) in branch.iter().skip(1).enumerate() {
         o = if index & (1 << i) == 0 {
             hash_sha(o.iter().chain(b.iter()).cloned().collect::<Vec<u8>>())
@@ -88,7 +85,7 @@ mod tests {
         let branch = mk_branch(&tree, 2);
         let expected_branch = vec![tree[6].clone(),tree[7].clone(), tree[2].clone()];
         assert_eq!(branch, expected_branch);
-        assert_eq!(verify_branch(&tree[1], 2, &branch), true);
+        assert!(verify_branch(&tree[1], 2, &branch));
     }
 }
 
@@ -0,0 +1,122 @@
+// MiMC, x_(i+1) = x_i^3 + k_i with round constants k_i, as in Vitalik's mimc_stark
+// https://vitalik.eth.limo/general/2018/07/21/starks_part_3.html
+// the round constants are a periodic column, so the verifier never sees more than 64 of them
+
+use crate::air::{Air, AirError, PeriodicColumn};
+use crate::assertion::Assertion;
+use crate::expression::Expr;
+use crate::field::FieldElement;
+use crate::prover::{prove, StarkProof};
+use crate::verifier::verify;
+
+pub const NUM_ROUND_CONSTANTS: usize = 64;
+
+// k_i = i^7 xor 42
+pub fn round_constants() -> Vec<FieldElement> {
+    (0..NUM_ROUND_CONSTANTS as u64).map(|i| FieldElement::new(i.pow(7) ^ 42)).collect()
+}
+
+pub struct MimcAir {
+    pub steps: usize,
+    pub input: FieldElement,
+    pub output: FieldElement,
+}
+
+impl Air for MimcAir {
+    fn steps(&self) -> usize {
+        self.steps
+    }
+
+    fn num_columns(&self) -> usize {
+        1
+    }
+
+    // x(g1 x) - x(x)^3 - k(x)
+    fn transition_constraints(&self) -> Vec<Expr> {
+        vec![Expr::next(0) - Expr::current(0).pow(3) - Expr::periodic(0)]
+    }
+
+    fn assertions(&self) -> Vec<Assertion> {
+        vec![
+            Assertion::single(0, 0, self.input),
+            Assertion::single(0, self.steps - 1, self.output),
+        ]
+    }
+
+    fn periodic_columns(&self) -> Vec<PeriodicColumn> {
+        vec![PeriodicColumn::new(round_constants())]
+    }
+}
+
+// the steps - 1 rounds of MiMC on input, with every intermediate value
+pub fn mimc_trace(input: FieldElement, steps: usize) -> Vec<FieldElement> {
+    let constants = round_constants();
+    let mut trace = vec![input];
+    for i in 0..steps - 1 {
+        let x = trace[i];
+        trace.push(x * x * x + constants[i % NUM_ROUND_CONSTANTS]);
+    }
+    trace
+}
+
+// prove the output of MiMC on input over steps rows, steps must be a multiple of the number
+// of round constants
+pub fn prove_mimc(input: FieldElement, steps: usize) -> Result<(FieldElement, StarkProof), AirError> {
+    if steps == 0 {
+        return Err(AirError::InvalidSteps(steps));
+    }
+    let trace = mimc_trace(input, steps);
+    let output = *trace.last().unwrap();
+    Ok((output, prove(&MimcAir { steps, input, output }, &[trace])?))
+}
+
+pub fn verify_mimc(input: FieldElement, output: FieldElement, steps: usize, proof: &StarkProof) -> bool {
+    verify(&MimcAir { steps, input, output }, proof)
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mimc() {
+        let steps = 128;
+        let input = FieldElement::new(3);
+        let (output, proof) = prove_mimc(input, steps).unwrap();
+        // the constraint has degree 3, so the composition is split in 2 segments
+        assert_eq!(MimcAir { steps, input, output }.transition_degrees(), vec![3]);
+        assert_eq!(proof.ood.composition.len(), 2);
+        assert!(verify_mimc(input, output, steps, &proof));
+        assert!(!verify_mimc(input, output + FieldElement::new(1), steps, &proof));
+        assert!(!verify_mimc(input + FieldElement::new(1), output, steps, &proof));
+    }
+
+    #[test]
+    fn test_wrong_round_constants() {
+        // a trace computed with other round constants doesn't verify
+        struct OtherConstants(MimcAir);
+        impl Air for OtherConstants {
+            fn steps(&self) -> usize { self.0.steps() }
+            fn num_columns(&self) -> usize { 1 }
+            fn transition_constraints(&self) -> Vec<Expr> { self.0.transition_constraints() }
+            fn assertions(&self) -> Vec<Assertion> { self.0.assertions() }
+            fn periodic_columns(&self) -> Vec<PeriodicColumn> {
+                vec![PeriodicColumn::new(round_constants().iter().map(|k| *k + FieldElement::new(1)).collect())]
+            }
+        }
+
+        let steps = 64;
+        let input = FieldElement::new(5);
+        let (output, proof) = prove_mimc(input, steps).unwrap();
+        assert!(!verify(&OtherConstants(MimcAir { steps, input, output }), &proof));
+    }
+
+    #[test]
+    fn test_prove_mimc_invalid_steps() {
+        let input = FieldElement::new(3);
+        assert_eq!(prove_mimc(input, 96).unwrap_err(), AirError::InvalidSteps(96));
+        // the round constants don't fit in 32 steps
+        assert_eq!(prove_mimc(input, 32).unwrap_err(), AirError::InvalidPeriodicColumn(0));
+    }
+}
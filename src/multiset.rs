@@ -0,0 +1,302 @@
+// permutation argument: the column B is a permutation of the public values a of the column A
+// after the commitment of A and B a challenge alpha is drawn, and the auxiliary column Z holds the
+// running product Z_0 = 1, Z_(i+1) = Z_i (alpha - a_i) / (alpha - b_i)
+// the transition holds on every step, the last one wrapping around to Z_0, so the product of
+// (alpha - a_i) / (alpha - b_i) over the trace is 1: prod (X - a_i) and prod (X - b_i) agree at
+// the random alpha, so they are equal except with probability steps / P
+// trace columns: A and B, and the auxiliary Z
+
+use crate::air::{Air, AirError, TransitionDomain};
+use crate::assertion::Assertion;
+use crate::field::FieldElement;
+use crate::prover::{prove, prove_zk, StarkProof};
+use crate::verifier::verify;
+use rand_core::RngCore;
+
+pub struct MultisetAir {
+    pub steps: usize,
+    pub a: Vec<FieldElement>,
+}
+
+impl Air for MultisetAir {
+    fn steps(&self) -> usize {
+        self.steps
+    }
+
+    fn num_columns(&self) -> usize {
+        2
+    }
+
+    fn transition_degrees(&self) -> Vec<usize> {
+        vec![2]
+    }
+
+    // Z(g1 x) (alpha - B(x)) - Z(x) (alpha - A(x))
+    fn evaluate_transition(&self, current: &[FieldElement], next: &[FieldElement], _periodic: &[FieldElement], challenges: &[FieldElement]) -> Vec<FieldElement> {
+        let alpha = challenges[0];
+        vec![next[2] * (alpha - current[1]) - current[2] * (alpha - current[0])]
+    }
+
+    fn transition_domains(&self) -> Vec<TransitionDomain> {
+        vec![TransitionDomain::All]
+    }
+
+    // A holds the public values, and the running product starts at 1
+    fn assertions(&self) -> Vec<Assertion> {
+        vec![
+            Assertion::sequence(0, 0, 1, self.a.clone()),
+            Assertion::single(2, 0, FieldElement::new(1)),
+        ]
+    }
+
+    fn num_aux_columns(&self) -> usize {
+        1
+    }
+
+    fn num_challenges(&self) -> usize {
+        1
+    }
+
+    fn build_aux_trace(&self, main: &[Vec<FieldElement>], challenges: &[FieldElement]) -> Vec<Vec<FieldElement>> {
+        vec![running_product(&main[0], &main[1], challenges[0])]
+    }
+}
+
+// Z_0 = 1 and Z_(i+1) = Z_i (alpha - a_i) / (alpha - b_i), one value per step
+fn running_product(a: &[FieldElement], b: &[FieldElement], alpha: FieldElement) -> Vec<FieldElement> {
+    let inv_b = FieldElement::multi_inv(&b.iter().map(|b| alpha - *b).collect::<Vec<_>>());
+    let mut z = vec![FieldElement::new(1)];
+    for i in 0..a.len() - 1 {
+        z.push(z[i] * (alpha - a[i]) * inv_b[i]);
+    }
+    z
+}
+
+// prove b is a permutation of the public a, both of steps values
+// a and b must hold steps values each, the columns of the trace
+pub fn make_proof_multiset(a: &[FieldElement], b: &[FieldElement], steps: u64) -> Result<StarkProof, AirError> {
+    if steps < 4 {
+        return Err(AirError::InvalidSteps(steps as usize));
+    }
+    prove(&MultisetAir { steps: steps as usize, a: a.to_vec() }, &[a.to_vec(), b.to_vec()])
+}
+
+// the same proof in zk mode, which reveals nothing about b, for at least MIN_ZK_STEPS steps
+pub fn make_zk_proof_multiset(a: &[FieldElement], b: &[FieldElement], steps: u64, rng: &mut impl RngCore) -> Result<StarkProof, AirError> {
+    if steps < 4 {
+        return Err(AirError::InvalidSteps(steps as usize));
+    }
+    prove_zk(&MultisetAir { steps: steps as usize, a: a.to_vec() }, &[a.to_vec(), b.to_vec()], rng)
+}
+
+// check the proof that the committed column is a permutation of a
+pub fn verify_multiset(proof: &StarkProof, a: &[FieldElement], steps: u64) -> bool {
+    a.len() == steps as usize && verify(&MultisetAir { steps: steps as usize, a: a.to_vec() }, proof)
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Domain;
+    use crate::lagrange::LagrangePoly;
+    use crate::merkle_tree::hash_sha;
+    use crate::poly::Poly;
+    use crate::prover::{
+        commit_columns, composition_degree, composition_evaluations, extend_trace, recombine_segments, extension_factor, MIN_ZK_STEPS,
+    };
+    use crate::transcript::Transcript;
+    use rand_core::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    #[test]
+    fn test_make_proof_multiset() {
+        let a = [FieldElement::new(1), FieldElement::new(2), FieldElement::new(4), FieldElement::new(3)];
+        let b = [FieldElement::new(4), FieldElement::new(2), FieldElement::new(3), FieldElement::new(1)];
+        let proof = make_proof_multiset(&a, &b, 4).unwrap();
+        // the DEEP composition has degree < 16, so FRI sends it directly and every position is opened
+        assert_eq!(proof.fri_proof.len(), 1);
+        assert_eq!(proof.trace_branches.len(), 4 * extension_factor as usize);
+        assert_eq!(proof.aux_trace_branches.len(), 4 * extension_factor as usize);
+        assert!(proof.aux_trace_root.is_some());
+        assert!(verify_multiset(&proof, &a, 4));
+
+        assert_eq!(make_proof_multiset(&a, &b[..3], 4).unwrap_err(), AirError::WrongColumnLength(1));
+        assert_eq!(make_proof_multiset(&a[..2], &b[..2], 2).unwrap_err(), AirError::InvalidSteps(2));
+    }
+
+    #[test]
+    fn test_running_product() {
+        let (a, b) = sample(8);
+        let alpha = FieldElement::new(123456789);
+        let z = running_product(&a, &b, alpha);
+        assert_eq!(z.len(), 8);
+        // the last transition wraps around to Z_0 = 1
+        assert_eq!(z[7] * (alpha - a[7]) / (alpha - b[7]), FieldElement::new(1));
+
+        let mut c = b.clone();
+        c[3] = c[4];
+        let z = running_product(&a, &c, alpha);
+        assert_ne!(z[7] * (alpha - a[7]) / (alpha - c[7]), FieldElement::new(1));
+    }
+
+    fn multiset_sample(steps: u64) -> (Vec<FieldElement>, Vec<FieldElement>) {
+        let a = (0..steps).map(|i| FieldElement::new(i * i + 1)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        b.reverse();
+        (a, b)
+    }
+
+    // the trace columns, main and auxiliary, extended over the coset, after the trace
+    // commitments are appended to the transcript
+    fn extended_columns(air: &MultisetAir, b: &[FieldElement], transcript: &mut Transcript) -> (Vec<Vec<FieldElement>>, Vec<FieldElement>) {
+        let main = extend_trace(&[air.a.clone(), b.to_vec()]);
+        transcript.append(&commit_columns(&main, &[])[1]);
+        let challenges = transcript.challenges(1);
+        let aux = extend_trace(&air.build_aux_trace(&[air.a.clone(), b.to_vec()], &challenges));
+        transcript.append(&commit_columns(&aux, &[])[1]);
+        ([main, aux].concat(), challenges)
+    }
+
+    #[test]
+    fn test_composition_degree() {
+        let steps = 32;
+        let (a, b) = multiset_sample(steps);
+        let air = MultisetAir { steps: steps as usize, a: a.clone() };
+        let offset = FieldElement::generator();
+
+        let mut transcript = Transcript::new(b"test");
+        let (columns, challenges) = extended_columns(&air, &b, &mut transcript);
+        let composition = composition_evaluations(&air, &columns, &challenges, false, &mut transcript);
+        let degree = LagrangePoly::new(composition, offset).to_poly().degree();
+        assert!(degree <= composition_degree(&air, false));
+
+        // when b isn't a permutation of a the running product doesn't wrap around to 1, so the
+        // composition isn't low degree
+        let mut bad_b = b.clone();
+        bad_b[5] += FieldElement::new(1);
+        let mut transcript = Transcript::new(b"test");
+        let (columns, challenges) = extended_columns(&air, &bad_b, &mut transcript);
+        let composition = composition_evaluations(&air, &columns, &challenges, false, &mut transcript);
+        let degree = LagrangePoly::new(composition, offset).to_poly().degree();
+        assert!(degree > composition_degree(&air, false));
+    }
+
+    #[test]
+    fn test_ood_evaluations() {
+        let steps = 16;
+        let (a, b) = multiset_sample(steps);
+        let proof = make_proof_multiset(&a, &b, steps).unwrap();
+        let air = MultisetAir { steps: steps as usize, a: a.clone() };
+        assert_eq!(proof.ood.trace.len(), 3);
+
+        // the out of domain evaluations are those of the interpolated columns
+        let mut transcript = Transcript::new(b"stark");
+        let (columns, challenges) = extended_columns(&air, &b, &mut transcript);
+        let composition = composition_evaluations(&air, &columns, &challenges, false, &mut transcript);
+        transcript.append(&proof.composition_root);
+        let z = transcript.challenge();
+        let g1 = Domain::root_of_unity(steps.trailing_zeros());
+        let p = Poly::new(Poly::inv_fft(&b, &g1));
+        assert_eq!(proof.ood.trace[1], p.eval(z));
+        assert_eq!(proof.ood.trace_next[1], p.eval(g1 * z));
+        let aux = LagrangePoly::new(columns[2].clone(), FieldElement::generator());
+        assert_eq!(proof.ood.trace[2], aux.eval(z));
+        let h = LagrangePoly::new(composition, FieldElement::generator()).eval(z);
+        assert_eq!(recombine_segments(&proof.ood.composition, z, steps as usize), h);
+    }
+
+    fn sample(steps: u64) -> (Vec<FieldElement>, Vec<FieldElement>) {
+        let a = (0..steps).map(|i| FieldElement::new(3 * i + 5)).collect::<Vec<_>>();
+        let mut b = a.clone();
+        b.rotate_left(1);
+        (a, b)
+    }
+
+    #[test]
+    fn test_verify_multiset() {
+        for steps in [4, 64] {
+            let (a, b) = sample(steps);
+            let proof = make_proof_multiset(&a, &b, steps).unwrap();
+            assert!(verify_multiset(&proof, &a, steps));
+            let mut other = a.clone();
+            other[1] += FieldElement::new(1);
+            assert!(!verify_multiset(&proof, &other, steps));
+        }
+    }
+
+    #[test]
+    fn test_verify_zk_multiset() {
+        let steps = 256;
+        let (a, b) = sample(steps);
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let proof = make_zk_proof_multiset(&a, &b, steps, &mut rng).unwrap();
+        assert!(proof.zk);
+        assert!(verify_multiset(&proof, &a, steps));
+
+        let mut c = b.clone();
+        c[10] = c[11];
+        let proof = make_zk_proof_multiset(&a, &c, steps, &mut rng).unwrap();
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        // below MIN_ZK_STEPS FRI would open too many rows of the trace for it to stay hidden
+        let (a, b) = sample(64);
+        let err = make_zk_proof_multiset(&a, &b, 64, &mut rng).unwrap_err();
+        assert_eq!(err, AirError::TooFewStepsForZk { steps: 64, required: MIN_ZK_STEPS });
+    }
+
+    #[test]
+    fn test_verify_not_a_permutation() {
+        // the a[i] / b[i] products of the two multisets are equal, but not the multisets
+        let steps = 4;
+        let a = [2, 3, 1, 1].map(FieldElement::new);
+        let b = [6, 1, 1, 1].map(FieldElement::new);
+        let proof = make_proof_multiset(&a, &b, steps).unwrap();
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        let steps = 64;
+        let (a, mut b) = sample(steps);
+        b[10] = b[11];
+        let proof = make_proof_multiset(&a, &b, steps).unwrap();
+        assert!(!verify_multiset(&proof, &a, steps));
+    }
+
+    #[test]
+    fn test_verify_tampered_proof() {
+        let steps = 64;
+        let (a, b) = sample(steps);
+
+        let mut proof = make_proof_multiset(&a, &b, steps).unwrap();
+        proof.ood.trace[1] += FieldElement::new(1);
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        let mut proof = make_proof_multiset(&a, &b, steps).unwrap();
+        proof.ood.composition[0] += FieldElement::new(1);
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        let mut proof = make_proof_multiset(&a, &b, steps).unwrap();
+        proof.trace_root = hash_sha(b"forged".to_vec());
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        let mut proof = make_proof_multiset(&a, &b, steps).unwrap();
+        proof.aux_trace_root = None;
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        // malformed proofs are rejected rather than panicking the verifier
+        let mut proof = make_proof_multiset(&a, &b, steps).unwrap();
+        proof.fri_proof.clear();
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        let mut proof = make_proof_multiset(&a, &b, steps).unwrap();
+        proof.fri_proof.remove(0);
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        let mut proof = make_proof_multiset(&a, &b, steps).unwrap();
+        proof.trace_branches[0].clear();
+        assert!(!verify_multiset(&proof, &a, steps));
+
+        let mut proof = make_proof_multiset(&a, &b, steps).unwrap();
+        proof.deep_root.truncate(4);
+        assert!(!verify_multiset(&proof, &a, steps));
+    }
+}
@@ -0,0 +1,277 @@
+// in place iterative number theoretic transforms over the 2-adic subgroups of the field
+// https://en.wikipedia.org/wiki/Cooley%E2%80%93Tukey_FFT_algorithm
+// with the parallel feature, the butterflies of each layer are spread over the rayon thread pool
+
+use std::sync::Arc;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::domain::{bit_reverse_index, Domain};
+use crate::field::FieldElement;
+
+// below this many butterflies a transform, or a block of one of its layers, runs serially
+// as the rayon overhead would outweigh the work
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 1 << 12;
+
+// twiddle factors of a transform, borrowed from the domain cache when the root is canonical
+enum Twiddles {
+    Cached(Arc<Domain>, bool),
+    Computed(Vec<FieldElement>),
+}
+
+impl Twiddles {
+    // twiddles [1, w, ..., w^(n/2-1)] with w = root, or w = root^-1 if inverse is set
+    fn new(root: FieldElement, n: usize, inverse: bool) -> Twiddles {
+        match Domain::from_root(root) {
+            Some(domain) if domain.size() == n => Twiddles::Cached(domain, inverse),
+            _ => {
+                let w = if inverse { root.inv() } else { root };
+                let mut twiddles = Vec::with_capacity(n / 2);
+                let mut x = FieldElement::new(1);
+                for _ in 0..n / 2 {
+                    twiddles.push(x);
+                    x *= w;
+                }
+                Twiddles::Computed(twiddles)
+            }
+        }
+    }
+
+    fn as_slice(&self) -> &[FieldElement] {
+        match self {
+            Twiddles::Cached(domain, false) => domain.twiddles(),
+            Twiddles::Cached(domain, true) => domain.inv_twiddles(),
+            Twiddles::Computed(twiddles) => twiddles,
+        }
+    }
+}
+
+// permute values in place so that values[i] goes to position bitrev(i)
+pub fn bit_reverse_permutation(values: &mut [FieldElement]) {
+    let n = values.len();
+    assert!(n.is_power_of_two(), "length must be a power of 2");
+    let log_n = n.trailing_zeros();
+    for i in 0..n {
+        let j = bit_reverse_index(i, log_n);
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+}
+
+// Cooley-Tukey butterfly: (u, v) -> (u + w v, u - w v)
+fn dit_butterfly(u: &mut FieldElement, v: &mut FieldElement, w: FieldElement) {
+    let x = *u;
+    let y = *v * w;
+    *u = x + y;
+    *v = x - y;
+}
+
+// Gentleman-Sande butterfly: (u, v) -> (u + v, (u - v) w)
+fn dif_butterfly(u: &mut FieldElement, v: &mut FieldElement, w: FieldElement) {
+    let x = *u;
+    let y = *v;
+    *u = x + y;
+    *v = (x - y) * w;
+}
+
+type Butterfly = fn(&mut FieldElement, &mut FieldElement, FieldElement);
+
+// apply the butterflies of one layer to a block of size len, pairing j with j + len/2
+// the j-th butterfly uses twiddles[j * step]
+fn layer_block(block: &mut [FieldElement], twiddles: &[FieldElement], step: usize, butterfly: Butterfly) {
+    let (lo, hi) = block.split_at_mut(block.len() / 2);
+    #[cfg(feature = "parallel")]
+    if lo.len() >= PARALLEL_THRESHOLD {
+        lo.par_iter_mut()
+            .zip(hi.par_iter_mut())
+            .enumerate()
+            .with_min_len(PARALLEL_THRESHOLD)
+            .for_each(|(j, (u, v))| butterfly(u, v, twiddles[j * step]));
+        return;
+    }
+    for (j, (u, v)) in lo.iter_mut().zip(hi.iter_mut()).enumerate() {
+        butterfly(u, v, twiddles[j * step]);
+    }
+}
+
+// apply one layer of butterflies to all the blocks of size len
+// large layers are split across blocks when they are small, and inside the blocks when they
+// are large, so every layer keeps all the threads busy
+fn layer(values: &mut [FieldElement], twiddles: &[FieldElement], len: usize, butterfly: Butterfly) {
+    let step = values.len() / len;
+    #[cfg(feature = "parallel")]
+    if values.len() / 2 >= PARALLEL_THRESHOLD {
+        values.par_chunks_mut(len)
+            .with_min_len((PARALLEL_THRESHOLD / len).max(1))
+            .for_each(|block| layer_block(block, twiddles, step, butterfly));
+        return;
+    }
+    for block in values.chunks_mut(len) {
+        layer_block(block, twiddles, step, butterfly);
+    }
+}
+
+// Cooley-Tukey decimation in time butterflies: bit reversed input, natural order output
+fn dit(values: &mut [FieldElement], twiddles: &[FieldElement]) {
+    let n = values.len();
+    let mut len = 2;
+    while len <= n {
+        layer(values, twiddles, len, dit_butterfly);
+        len *= 2;
+    }
+}
+
+// Gentleman-Sande decimation in frequency butterflies: natural order input, bit reversed output
+fn dif(values: &mut [FieldElement], twiddles: &[FieldElement]) {
+    let n = values.len();
+    let mut len = n;
+    while len >= 2 {
+        layer(values, twiddles, len, dif_butterfly);
+        len /= 2;
+    }
+}
+
+fn scale_by_inverse_length(values: &mut [FieldElement]) {
+    let invlen = FieldElement::new(values.len() as u64).inv();
+    for x in values.iter_mut() {
+        *x *= invlen;
+    }
+}
+
+// evaluate the coefficients in values at the powers of root, root must be a primitive
+// n-th root of unity where n = values.len()
+// natural order input and output, same result as Poly::fft
+pub fn ntt(values: &mut [FieldElement], root: FieldElement) {
+    bit_reverse_permutation(values);
+    dit(values, Twiddles::new(root, values.len(), false).as_slice());
+}
+
+// same as ntt, but the output is left in bit reversed order, avoiding the permutation
+pub fn ntt_bit_reversed(values: &mut [FieldElement], root: FieldElement) {
+    assert!(values.len().is_power_of_two(), "length must be a power of 2");
+    dif(values, Twiddles::new(root, values.len(), false).as_slice());
+}
+
+// interpolate the evaluations in values at the powers of root back into coefficients
+// natural order input and output, same result as Poly::inv_fft
+pub fn intt(values: &mut [FieldElement], root: FieldElement) {
+    bit_reverse_permutation(values);
+    dit(values, Twiddles::new(root, values.len(), true).as_slice());
+    scale_by_inverse_length(values);
+}
+
+// inverse of ntt_bit_reversed: bit reversed evaluations in, natural order coefficients out
+pub fn intt_bit_reversed(values: &mut [FieldElement], root: FieldElement) {
+    assert!(values.len().is_power_of_two(), "length must be a power of 2");
+    dit(values, Twiddles::new(root, values.len(), true).as_slice());
+    scale_by_inverse_length(values);
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::poly::Poly;
+
+    fn sample(n: usize) -> Vec<FieldElement> {
+        (0..n as u64).map(|i| FieldElement::new(i * i + 7 * i + 3)).collect()
+    }
+
+    // evaluate at every power of root without any FFT, for test purposes
+    fn naive_eval(coeffs: &[FieldElement], root: FieldElement) -> Vec<FieldElement> {
+        let mut o = vec![];
+        let mut x = FieldElement::new(1);
+        for _ in 0..coeffs.len() {
+            o.push(coeffs.iter().rev().fold(FieldElement::new(0), |acc, c| acc * x + *c));
+            x *= root;
+        }
+        o
+    }
+
+    #[test]
+    fn test_bit_reverse_permutation() {
+        let mut values = sample(8);
+        let original = values.clone();
+        bit_reverse_permutation(&mut values);
+        assert_eq!(values[1], original[4]);
+        assert_eq!(values[3], original[6]);
+        bit_reverse_permutation(&mut values);
+        assert_eq!(values, original);
+    }
+
+    #[test]
+    fn test_ntt() {
+        for log_n in 0..8 {
+            let n = 1 << log_n;
+            let root = FieldElement::nth_root_of_unity(n as u64);
+            let mut values = sample(n);
+            let expected = naive_eval(&values, root);
+            ntt(&mut values, root);
+            assert_eq!(values, expected);
+        }
+    }
+
+    #[test]
+    fn test_ntt_matches_poly_fft() {
+        let root = FieldElement::nth_root_of_unity(64);
+        let coeffs = sample(64);
+        let mut values = coeffs.clone();
+        ntt(&mut values, root);
+        assert_eq!(values, Poly::fft(&coeffs, &root));
+
+        intt(&mut values, root);
+        assert_eq!(values, coeffs);
+    }
+
+    #[test]
+    fn test_ntt_non_canonical_root() {
+        let root = FieldElement::nth_root_of_unity(16).inv();
+        let mut values = sample(16);
+        let expected = naive_eval(&values, root);
+        ntt(&mut values, root);
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_ntt_bit_reversed() {
+        let root = FieldElement::nth_root_of_unity(32);
+        let coeffs = sample(32);
+
+        let mut natural = coeffs.clone();
+        ntt(&mut natural, root);
+
+        let mut reversed = coeffs.clone();
+        ntt_bit_reversed(&mut reversed, root);
+        bit_reverse_permutation(&mut reversed);
+        assert_eq!(reversed, natural);
+
+        bit_reverse_permutation(&mut reversed);
+        intt_bit_reversed(&mut reversed, root);
+        assert_eq!(reversed, coeffs);
+    }
+
+    #[test]
+    fn test_ntt_large() {
+        // big enough to go through the parallel layers when the feature is on
+        let n = 1 << 15;
+        let root = FieldElement::nth_root_of_unity(n as u64);
+        let coeffs = sample(n);
+        let mut values = coeffs.clone();
+        ntt(&mut values, root);
+        for i in [0, 1, 12345, n - 1] {
+            let x = root.pow(i as u64);
+            assert_eq!(values[i], coeffs.iter().rev().fold(FieldElement::new(0), |acc, c| acc * x + *c));
+        }
+
+        let mut reversed = coeffs.clone();
+        ntt_bit_reversed(&mut reversed, root);
+        bit_reverse_permutation(&mut reversed);
+        assert_eq!(reversed, values);
+
+        intt(&mut values, root);
+        assert_eq!(values, coeffs);
+    }
+}
@@ -1,162 +1,503 @@
 // implement polynomials using the FieldElement type
 
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Neg};
+
+use crate::domain::Domain;
 use crate::field::FieldElement;
-use crate::field::P;
+use crate::ntt;
+use crate::subproduct_tree::SubproductTree;
 
-// implement a polynomial type
-// enum 
-// 1. coeffs
-// 2. lagrange
+// below this degree or number of points, evaluating point by point is faster than the subproduct tree
+const MULTIPOINT_THRESHOLD: usize = 128;
 
+// below this size (of the smallest operand) we multiply with the schoolbook algorithm,
+// then with Karatsuba up to the NTT threshold
+const KARATSUBA_THRESHOLD: usize = 32;
+const NTT_THRESHOLD: usize = 256;
 
+// below this degree division uses the schoolbook long division
+const NEWTON_DIVISION_THRESHOLD: usize = 256;
 
-// Poly is a polynomial of degree n
-#[derive(Copy, Clone)]
-pub struct Poly {
-    pub coeffs: [FieldElement; 256],
-    // type can be coeffs or lagrange
-    pub lagrange : bool
+fn mul_schoolbook(a: &[FieldElement], b: &[FieldElement]) -> Vec<FieldElement> {
+    let mut result = vec![FieldElement::new(0); a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            result[i+j] += *x * *y;
+        }
+    }
+    result
+}
+
+fn add_slices(a: &[FieldElement], b: &[FieldElement]) -> Vec<FieldElement> {
+    (0..a.len().max(b.len()))
+        .map(|i| a.get(i).copied().unwrap_or_default() + b.get(i).copied().unwrap_or_default())
+        .collect()
+}
+
+// (a0 + x^m a1) * (b0 + x^m b1) = a0 b0 + x^m ((a0 + a1)(b0 + b1) - a0 b0 - a1 b1) + x^2m a1 b1
+fn mul_karatsuba(a: &[FieldElement], b: &[FieldElement]) -> Vec<FieldElement> {
+    if a.len().min(b.len()) <= KARATSUBA_THRESHOLD {
+        return mul_schoolbook(a, b);
+    }
 
+    let m = a.len().max(b.len()) / 2;
+    let (a0, a1) = a.split_at(m.min(a.len()));
+    let (b0, b1) = b.split_at(m.min(b.len()));
+    if a1.is_empty() || b1.is_empty() {
+        // unbalanced operands, split only the longest one
+        let (short, long0, long1) = if a1.is_empty() { (a, b0, b1) } else { (b, a0, a1) };
+        let low = mul_karatsuba(short, long0);
+        let high = mul_karatsuba(short, long1);
+        let mut result = vec![FieldElement::new(0); a.len() + b.len() - 1];
+        for (i, x) in low.iter().enumerate() {
+            result[i] += *x;
+        }
+        for (i, x) in high.iter().enumerate() {
+            result[i + m] += *x;
+        }
+        return result;
+    }
+
+    let z0 = mul_karatsuba(a0, b0);
+    let z2 = mul_karatsuba(a1, b1);
+    let z1 = mul_karatsuba(&add_slices(a0, a1), &add_slices(b0, b1));
+
+    let mut result = vec![FieldElement::new(0); a.len() + b.len() - 1];
+    for (i, x) in z0.iter().enumerate() {
+        result[i] += *x;
+        result[i + m] -= *x;
+    }
+    for (i, x) in z2.iter().enumerate() {
+        result[i + 2 * m] += *x;
+        result[i + m] -= *x;
+    }
+    for (i, x) in z1.iter().enumerate() {
+        // z1 may have trailing zero terms past the product length
+        if i + m < result.len() {
+            result[i + m] += *x;
+        }
+    }
+    result
+}
+
+// pointwise product of the evaluations on a subgroup large enough to hold the product
+fn mul_ntt(a: &[FieldElement], b: &[FieldElement]) -> Vec<FieldElement> {
+    let len = a.len() + b.len() - 1;
+    let n = len.next_power_of_two();
+    let root = Domain::root_of_unity(n.trailing_zeros());
+
+    let mut fa = a.to_vec();
+    fa.resize(n, FieldElement::new(0));
+    ntt::ntt(&mut fa, root);
+
+    let mut fb = b.to_vec();
+    fb.resize(n, FieldElement::new(0));
+    ntt::ntt(&mut fb, root);
+
+    for (x, y) in fa.iter_mut().zip(fb.iter()) {
+        *x *= *y;
+    }
+    ntt::intt(&mut fa, root);
+    fa.truncate(len);
+    fa
+}
+
+fn mul_slices(a: &[FieldElement], b: &[FieldElement]) -> Vec<FieldElement> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let smallest = a.len().min(b.len());
+    if smallest <= KARATSUBA_THRESHOLD {
+        mul_schoolbook(a, b)
+    } else if smallest < NTT_THRESHOLD {
+        mul_karatsuba(a, b)
+    } else {
+        mul_ntt(a, b)
+    }
+}
+
+// first k coefficients of 1/f as a power series, f[0] must be non zero
+// Newton iteration g <- g * (2 - f * g) doubles the number of correct coefficients
+fn inverse_series(f: &[FieldElement], k: usize) -> Vec<FieldElement> {
+    let mut g = vec![f[0].inv()];
+    let mut precision = 1;
+    while precision < k {
+        precision = (2 * precision).min(k);
+        let mut fg = mul_slices(&f[..precision.min(f.len())], &g);
+        fg.truncate(precision);
+        for x in fg.iter_mut() {
+            *x = -*x;
+        }
+        fg[0] += FieldElement::new(2);
+        g = mul_slices(&g, &fg);
+        g.truncate(precision);
+    }
+    g
+}
+
+// Poly is a polynomial of degree n, stored by its coefficients in increasing degree order
+// leading zero coefficients are trimmed, so the zero polynomial has no coefficients
+// see lagrange.rs for polynomials given by their evaluations
+#[derive(Clone, Debug, PartialEq)]
+pub struct Poly {
+    pub coeffs: Vec<FieldElement>,
 }
 
 
 impl Poly {
     
-    pub fn new(coeffs: [FieldElement; 256]) -> Poly {
-        Poly { coeffs: coeffs, lagrange: false }
+    pub fn new(coeffs: Vec<FieldElement>) -> Poly {
+        let mut poly = Poly { coeffs };
+        poly.trim();
+        poly
     }
 
-    pub fn add(&self, other: Poly) -> Poly {
-        let mut result = [FieldElement::new(0); 256];
-        for i in 0..256 {
-            result[i] = self.coeffs[i] + other.coeffs[i];
+    pub fn zero() -> Poly {
+        Poly::new(vec![])
+    }
+
+    // remove the leading zero coefficients
+    pub fn trim(&mut self) {
+        while self.coeffs.last() == Some(&FieldElement::new(0)) {
+            self.coeffs.pop();
         }
-        Poly::new(result)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coeffs.is_empty()
+    }
+
+    // degree of the polynomial, the zero polynomial has degree 0 by convention
+    pub fn degree(&self) -> usize {
+        self.coeffs.len().saturating_sub(1)
+    }
+
+    // coefficient of x^i, zero past the degree
+    pub fn coeff(&self, i: usize) -> FieldElement {
+        self.coeffs.get(i).copied().unwrap_or_default()
+    }
+
+    pub fn add(&self, other: Poly) -> Poly {
+        self + &other
     }
 
     pub fn sub(&self, other: Poly) -> Poly {
-        let mut result = [FieldElement::new(0); 256];
-        for i in 0..256 {
-            result[i] = self.coeffs[i]- other.coeffs[i];
-        }
-        Poly::new(result)
+        self - &other
     }
 
     pub fn mul(&self, other: FieldElement) -> Poly {
-        let mut result = [FieldElement::new(0); 256];
-        for i in 0..256 {
-            result[i] = self.coeffs[i]*other;
-            
+        self * other
+    }
+
+    // composition p(q(x)), with Horner's rule over polynomials
+    pub fn compose(&self, q: &Poly) -> Poly {
+        let mut result = Poly::zero();
+        for c in self.coeffs.iter().rev() {
+            result = &(&result * q) + &Poly::new(vec![*c]);
         }
-        Poly::new(result)
+        result
     }
 
+    // p(g * x), which has coefficients c_i * g^i
+    // with g the generator of the trace domain, this is the "next row" of a trace polynomial
+    pub fn shift(&self, g: FieldElement) -> Poly {
+        let mut g_power = FieldElement::new(1);
+        let mut coeffs = Vec::with_capacity(self.coeffs.len());
+        for c in self.coeffs.iter() {
+            coeffs.push(*c * g_power);
+            g_power *= g;
+        }
+        Poly::new(coeffs)
+    }
+
+    // formal derivative
+    pub fn derivative(&self) -> Poly {
+        Poly::new(self.coeffs.iter().enumerate().skip(1).map(|(i, c)| *c * FieldElement::new(i as u64)).collect())
+    }
+
+    // full product, with schoolbook, Karatsuba or NTT multiplication depending on the sizes
     pub fn mul_poly(&self, other: Poly) -> Poly {
-        let mut result = [FieldElement::new(0); 256];
-        for i in 0..256 {
-            for j in 0..256 {
-                if i+j < 256 {
-                    result[i+j] = result[i+j]+(self.coeffs[i]*other.coeffs[j]);
-                }
+        Poly::new(mul_slices(&self.coeffs, &other.coeffs))
+    }
+
+    pub fn square(&self) -> Poly {
+        Poly::new(mul_slices(&self.coeffs, &self.coeffs))
+    }
+
+    // self^k by square and multiply
+    pub fn pow(&self, k: u64) -> Poly {
+        let mut result = Poly::new(vec![FieldElement::new(1)]);
+        let mut base = self.clone();
+        let mut k = k;
+        while k > 0 {
+            if k % 2 == 1 {
+                result = result.mul_poly(base.clone());
+            }
+            k >>= 1;
+            if k > 0 {
+                base = base.square();
             }
-            
         }
-        Poly::new(result)
+        result
     }
 
+    // long division, returns (quotient, remainder) with deg remainder < deg divisor
+    pub fn div_rem(&self, divisor: &Poly) -> (Poly, Poly) {
+        assert!(!divisor.is_zero(), "division by the zero polynomial");
+        let d = divisor.degree();
+        if self.coeffs.len() <= d {
+            return (Poly::zero(), self.clone());
+        }
+
+        // for large operands, compute the quotient with a Newton inversion, which only
+        // needs multiplications and benefits from the NTT
+        if d >= NEWTON_DIVISION_THRESHOLD && self.coeffs.len() - d >= NEWTON_DIVISION_THRESHOLD {
+            let quotient = self.newton_quotient(divisor);
+            let remainder = self.sub(quotient.mul_poly(divisor.clone()));
+            return (quotient, remainder);
+        }
 
+        self.div_rem_schoolbook(divisor)
+    }
+
+    fn div_rem_schoolbook(&self, divisor: &Poly) -> (Poly, Poly) {
+        let d = divisor.degree();
+        let lead_inv = divisor.coeffs[d].inv();
+        let mut remainder = self.coeffs.clone();
+        let mut quotient = vec![FieldElement::new(0); self.coeffs.len() - d];
+        for i in (0..quotient.len()).rev() {
+            // cancel the current leading coefficient of the remainder
+            let c = remainder[i + d] * lead_inv;
+            quotient[i] = c;
+            for (j, dc) in divisor.coeffs.iter().enumerate() {
+                remainder[i + j] -= c * *dc;
+            }
+        }
+        remainder.truncate(d);
+        (Poly::new(quotient), Poly::new(remainder))
+    }
+
+    // with rev(p) = x^deg(p) * p(1/x), self = q * divisor + r gives
+    // rev(q) = rev(self) / rev(divisor) mod x^(deg self - deg divisor + 1)
+    fn newton_quotient(&self, divisor: &Poly) -> Poly {
+        let k = self.coeffs.len() - divisor.degree();
+        let rev_self = self.coeffs.iter().rev().copied().collect::<Vec<_>>();
+        let rev_divisor = divisor.coeffs.iter().rev().copied().collect::<Vec<_>>();
+
+        let inverse = inverse_series(&rev_divisor, k);
+        let mut rev_quotient = mul_slices(&rev_self[..k], &inverse);
+        rev_quotient.resize(k, FieldElement::new(0));
+        rev_quotient.reverse();
+        Poly::new(rev_quotient)
+    }
+
+    // the vanishing polynomial x^n - 1 of the subgroup of size n
+    pub fn vanishing(n: usize) -> Poly {
+        let mut coeffs = vec![FieldElement::new(0); n + 1];
+        coeffs[0] = -FieldElement::new(1);
+        coeffs[n] = FieldElement::new(1);
+        Poly::new(coeffs)
+    }
+
+    // division by x^n - 1 in O(deg), returns (quotient, remainder)
+    pub fn div_rem_vanishing(&self, n: usize) -> (Poly, Poly) {
+        assert!(n > 0, "x^0 - 1 is the zero polynomial");
+        if self.coeffs.len() <= n {
+            return (Poly::zero(), self.clone());
+        }
+
+        // subtracting c * x^(i-n) * (x^n - 1) removes c * x^i and adds c * x^(i-n)
+        let mut remainder = self.coeffs.clone();
+        let mut quotient = vec![FieldElement::new(0); self.coeffs.len() - n];
+        for i in (n..remainder.len()).rev() {
+            let c = remainder[i];
+            quotient[i - n] = c;
+            remainder[i - n] += c;
+        }
+        remainder.truncate(n);
+        (Poly::new(quotient), Poly::new(remainder))
+    }
+
+    // exact division by x^n - 1, None if the polynomial does not vanish on the subgroup of size n
+    pub fn div_by_vanishing(&self, n: usize) -> Option<Poly> {
+        let (quotient, remainder) = self.div_rem_vanishing(n);
+        if remainder.is_zero() {
+            Some(quotient)
+        } else {
+            None
+        }
+    }
+
+    // exact division by the sparse vanishing polynomial (x^n - 1) / prod (x - e), e in exceptions,
+    // i.e. the polynomial only has to vanish on the subgroup of size n minus the exceptions
+    // e.g. the transition constraints don't apply to the last step g^(n-1)
+    pub fn div_by_vanishing_except(&self, n: usize, exceptions: &[FieldElement]) -> Option<Poly> {
+        let mut numerator = self.clone();
+        for e in exceptions {
+            numerator = numerator.mul_poly(Poly::new(vec![-*e, FieldElement::new(1)]));
+        }
+        numerator.div_by_vanishing(n)
+    }
+
+    // evaluations of x^n - 1 on the coset offset * <g> of size domain_size, n dividing domain_size
+    // (offset * g^i)^n = offset^n * (g^n)^i and g^n has order domain_size / n, so there are only
+    // domain_size / n distinct values which repeat along the coset
+    pub fn eval_vanishing_on_coset(n: usize, offset: FieldElement, domain_size: usize) -> Vec<FieldElement> {
+        assert!(domain_size.is_power_of_two() && domain_size.is_multiple_of(n), "n must divide the domain size");
+        let blowup = domain_size / n;
+        let g_n = Domain::root_of_unity(blowup.trailing_zeros());
+        let offset_n = offset.pow(n as u64);
+
+        let mut distinct = Vec::with_capacity(blowup);
+        let mut x = offset_n;
+        for _ in 0..blowup {
+            distinct.push(x - FieldElement::new(1));
+            x *= g_n;
+        }
+        (0..domain_size).map(|i| distinct[i % blowup]).collect()
+    }
+
+    // evaluate with Horner's rule: c_0 + x * (c_1 + x * (c_2 + ...))
     pub fn eval(&self, x: FieldElement) -> FieldElement {
         let mut result = FieldElement::new(0);
-        for i in 0..256 {
-            result = result+(self.coeffs[i]*x.pow(i as u64));
+        for c in self.coeffs.iter().rev() {
+            result = result * x + *c;
         }
         result
     }
 
+    // evaluate at every point of xs, with Horner's rule for small inputs and with
+    // a subproduct tree when both the degree and the number of points are large
+    // for points forming a subgroup or a coset, Poly::coset_fft is faster
+    pub fn eval_many(&self, xs: &[FieldElement]) -> Vec<FieldElement> {
+        if self.degree() < MULTIPOINT_THRESHOLD || xs.len() < MULTIPOINT_THRESHOLD {
+            xs.iter().map(|x| self.eval(*x)).collect()
+        } else {
+            self.multipoint_eval(xs)
+        }
+    }
+
+    // evaluate at arbitrary points by reducing modulo the subproduct tree of the points
+    pub fn multipoint_eval(&self, xs: &[FieldElement]) -> Vec<FieldElement> {
+        if xs.is_empty() {
+            return vec![];
+        }
+        SubproductTree::new(xs).eval(self)
+    }
+
+    // interpolate the polynomial of degree < n through the n points (x_i, y_i), in O(n^2)
+    // the x coordinates must be distinct
     pub fn lagrange_interpolation(x: &[FieldElement], y:&[FieldElement]) -> Poly {
         assert_eq!(x.len(), y.len());
 
-        let coeffs = [FieldElement::new(0); 256];
-        let mut result = Poly::new(coeffs);
+        // M(X) = prod (X - Xj), the numerator of the i-th basis polynomial is M(X) / (X - Xi)
+        let mut m = Poly::new(vec![FieldElement::new(1)]);
+        for xj in x {
+            m = m.mul_poly(Poly::new(vec![-*xj, FieldElement::new(1)]));
+        }
+
+        let mut result = vec![FieldElement::new(0); x.len()];
         for i in 0..x.len() {
-            let mut coeffs = [FieldElement::new(0); 256];
-            coeffs[0] = FieldElement::new(1);
-            let mut numerator = Poly::new(coeffs);
+            let (numerator, _) = m.div_rem(&Poly::new(vec![-x[i], FieldElement::new(1)]));
 
             let mut denominator = FieldElement::new(1);
-            for j in 0..y.len() {
+            for j in 0..x.len() {
                 if i != j {
-
-                    //X - Xj
-                    let mut coeffs = [FieldElement::new(0); 256];
-                    coeffs[0] = FieldElement::new(P-(j as u64));
-                    coeffs[1] = FieldElement::new(1);
-                    let poly = Poly::new(coeffs);
-                    
-                    numerator = numerator.mul_poly(poly);
-                    denominator = denominator*(FieldElement::new(i as u64)-FieldElement::new(j as u64));
+                    denominator *= x[i] - x[j];
                 }
             }
-            result = result.add(numerator.mul(y[i]).mul(denominator.inv()));
+            assert!(denominator != FieldElement::new(0), "interpolation points must be distinct");
+
+            let scale = y[i] * denominator.inv();
+            for (r, c) in result.iter_mut().zip(numerator.coeffs.iter()) {
+                *r += *c * scale;
+            }
         }
-        result
+        Poly::new(result)
+    }
+
+    // same as lagrange_interpolation, in O(n log^2 n) with a subproduct tree, for large point sets
+    pub fn fast_interpolation(x: &[FieldElement], y: &[FieldElement]) -> Poly {
+        assert_eq!(x.len(), y.len());
+        if x.is_empty() {
+            return Poly::zero();
+        }
+        SubproductTree::new(x).interpolate(y)
     }
 
 
     // fast fourier transform
     // https://en.wikipedia.org/wiki/Fast_Fourier_transform
+    // computed in place with the iterative NTT, see ntt.rs
     
     pub fn fft(vals: &[FieldElement], root_of_unity: &FieldElement) -> Vec<FieldElement> {
-        let n = vals.len();
-        if n == 1 {
-            return vals.to_vec();
-        }
-
-        let root_of_unity_sqr = (*root_of_unity)*(*root_of_unity);
-        let l = Self::fft(&vals.iter().step_by(2).map(|&x| x).collect::<Vec<FieldElement>>(), &root_of_unity_sqr);
-        let r = Self::fft(&vals.iter().skip(1).step_by(2).map(|&x| x).collect::<Vec<FieldElement>>(), &root_of_unity_sqr);
-
-        let mut o = vec![FieldElement::new(0); n];
-        let mut power_of_root_of_unity = FieldElement::new(1);
-            
-        for i in 0..n/2 {
-            let y_times_root = r[i]*power_of_root_of_unity;
-            o[i] = l[i]+(y_times_root);
-            o[i+n/2] = l[i]-(y_times_root);
-            power_of_root_of_unity = power_of_root_of_unity*(*root_of_unity);
-        }
+        let mut o = vals.to_vec();
+        ntt::ntt(&mut o, *root_of_unity);
         o
     }
 
     // Naive FFT function, for test purposes
+    #[cfg(test)]
     fn naive_fft(vals: &[FieldElement], root_of_unity: &FieldElement) -> Vec<FieldElement> {
         let n = vals.len();
         let mut o = vec![FieldElement::new(0); n];
         let mut power_of_root_of_unity = FieldElement::new(1);
 
-        for i in 0..n {
-            for j in 0..n {
-                o[i] = o[i]+(vals[j]*power_of_root_of_unity.pow(j as u64));
+        for o_i in o.iter_mut() {
+            for (j, v) in vals.iter().enumerate() {
+                *o_i += *v*power_of_root_of_unity.pow(j as u64);
             }
-            power_of_root_of_unity = power_of_root_of_unity*(*root_of_unity);
+            power_of_root_of_unity *= *root_of_unity;
         }
         o
     }
 
     // Inverse FFT function
     pub fn inv_fft(vals: &[FieldElement], root_of_unity: &FieldElement) -> Vec<FieldElement> {
-        let n = vals.len();
+        let mut o = vals.to_vec();
+        ntt::intt(&mut o, *root_of_unity);
+        o
+    }
 
-        // Inverse FFT
-        let invlen = FieldElement::new(vals.len() as u64).inv();
-        let inv_root_of_unity = root_of_unity.inv();
-        print!("invlen: {:?}" , invlen);
-        let poly = Self::fft(&vals, &inv_root_of_unity);
-        print!("poly: {:?}" , poly);
-        // for each element of poly, multiply by invlen
-        let inv_poly: Vec<FieldElement> = poly.iter().map(|x| (*x * invlen)).collect();
-        inv_poly
+    // evaluate the polynomial given by coeffs on the coset offset * <root>
+    // p(offset * x) has coefficients c_i * offset^i, so we scale then run a plain FFT
+    // coeffs shorter than the domain are zero padded
+    pub fn coset_fft(coeffs: &[FieldElement], offset: FieldElement, root_of_unity: &FieldElement) -> Vec<FieldElement> {
+        let n = match Domain::from_root(*root_of_unity) {
+            Some(domain) => domain.size(),
+            None => Domain::power_cycle(*root_of_unity).len(),
+        };
+        assert!(coeffs.len() <= n, "more coefficients than points in the domain");
+        let mut o = vec![FieldElement::new(0); n];
+        let mut offset_power = FieldElement::new(1);
+        for (i, c) in coeffs.iter().enumerate() {
+            o[i] = *c * offset_power;
+            offset_power *= offset;
+        }
+        ntt::ntt(&mut o, *root_of_unity);
+        o
+    }
+
+    // interpolate evaluations on the coset offset * <root> back into coefficients
+    pub fn coset_inv_fft(vals: &[FieldElement], offset: FieldElement, root_of_unity: &FieldElement) -> Vec<FieldElement> {
+        let mut o = Self::inv_fft(vals, root_of_unity);
+        let inv_offset = offset.inv();
+        let mut offset_power = FieldElement::new(1);
+        for c in o.iter_mut() {
+            *c *= offset_power;
+            offset_power *= inv_offset;
+        }
+        o
+    }
 
+    // low degree extension: evals are the evaluations of a polynomial on the subgroup of
+    // size n = evals.len(), return its evaluations on the coset offset * <g> of size n * blowup
+    pub fn low_degree_extend(evals: &[FieldElement], blowup: usize, offset: FieldElement) -> Vec<FieldElement> {
+        let n = evals.len();
+        assert!(n.is_power_of_two() && blowup.is_power_of_two(), "sizes must be powers of 2");
+        let coeffs = Self::inv_fft(evals, &Domain::root_of_unity(n.trailing_zeros()));
+        Self::coset_fft(&coeffs, offset, &Domain::root_of_unity((n * blowup).trailing_zeros()))
     }
 
     
@@ -165,52 +506,388 @@ impl Poly {
     
 }
 
+impl Add<&Poly> for &Poly {
+    type Output = Poly;
+
+    fn add(self, other: &Poly) -> Poly {
+        let len = self.coeffs.len().max(other.coeffs.len());
+        Poly::new((0..len).map(|i| self.coeff(i) + other.coeff(i)).collect())
+    }
+}
+
+impl Sub<&Poly> for &Poly {
+    type Output = Poly;
+
+    fn sub(self, other: &Poly) -> Poly {
+        let len = self.coeffs.len().max(other.coeffs.len());
+        Poly::new((0..len).map(|i| self.coeff(i) - other.coeff(i)).collect())
+    }
+}
+
+impl Mul<&Poly> for &Poly {
+    type Output = Poly;
+
+    fn mul(self, other: &Poly) -> Poly {
+        Poly::new(mul_slices(&self.coeffs, &other.coeffs))
+    }
+}
+
+// implement the operators on owned polynomials on top of the reference implementations above
+macro_rules! forward_poly_ops {
+    ($op:ident, $method:ident) => {
+        impl $op<Poly> for Poly {
+            type Output = Poly;
+
+            fn $method(self, other: Poly) -> Poly {
+                $op::$method(&self, &other)
+            }
+        }
+
+        impl $op<&Poly> for Poly {
+            type Output = Poly;
+
+            fn $method(self, other: &Poly) -> Poly {
+                $op::$method(&self, other)
+            }
+        }
+
+        impl $op<Poly> for &Poly {
+            type Output = Poly;
+
+            fn $method(self, other: Poly) -> Poly {
+                $op::$method(self, &other)
+            }
+        }
+    };
+}
+
+forward_poly_ops!(Add, add);
+forward_poly_ops!(Sub, sub);
+forward_poly_ops!(Mul, mul);
+
+// multiplication by a scalar
+impl Mul<FieldElement> for &Poly {
+    type Output = Poly;
+
+    fn mul(self, other: FieldElement) -> Poly {
+        Poly::new(self.coeffs.iter().map(|c| *c * other).collect())
+    }
+}
+
+impl Mul<FieldElement> for Poly {
+    type Output = Poly;
+
+    fn mul(self, other: FieldElement) -> Poly {
+        &self * other
+    }
+}
+
+impl Neg for &Poly {
+    type Output = Poly;
+
+    fn neg(self) -> Poly {
+        Poly::new(self.coeffs.iter().map(|c| -*c).collect())
+    }
+}
+
+impl Neg for Poly {
+    type Output = Poly;
+
+    fn neg(self) -> Poly {
+        -&self
+    }
+}
+
+// displays as a + bx + cx^2, skipping the zero coefficients
+impl fmt::Display for Poly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut first = true;
+        for (i, c) in self.coeffs.iter().enumerate() {
+            if *c == FieldElement::new(0) {
+                continue;
+            }
+            if !first {
+                write!(f, " + ")?;
+            }
+            first = false;
+            let one = *c == FieldElement::new(1);
+            match i {
+                0 => write!(f, "{}", c)?,
+                1 if one => write!(f, "x")?,
+                1 => write!(f, "{}x", c)?,
+                _ if one => write!(f, "x^{}", i)?,
+                _ => write!(f, "{}x^{}", c, i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 //tests
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::field::P;
 
     #[test]
     fn test_add() {
-        let a = Poly::new([FieldElement::new(1);256]);
-        let b = Poly::new([FieldElement::new(2); 256]);
+        let a = Poly::new(vec![FieldElement::new(1);256]);
+        let b = Poly::new(vec![FieldElement::new(2); 256]);
         let c = a.add(b);
         assert_eq!(c.coeffs[0], FieldElement::new(3));
     }
 
     #[test]
     fn test_sub() {
-        let a = Poly::new([FieldElement::new(1);256]);
-        let b = Poly::new([FieldElement::new(2); 256]);
+        let a = Poly::new(vec![FieldElement::new(1);256]);
+        let b = Poly::new(vec![FieldElement::new(2); 256]);
         let c = a.sub(b);
         assert_eq!(c.coeffs[0], FieldElement::new(0xffffffff00000000));
     }
 
     #[test]
     fn test_eval() {
-        let mut a = Poly::new([FieldElement::new(0);256]);
-        a.coeffs[0] = FieldElement::new(2);
-        a.coeffs[1] = FieldElement::new(1);
+        let a = Poly::new(vec![FieldElement::new(2), FieldElement::new(1)]);
 
         assert_eq!(a.eval(FieldElement::new(1)), FieldElement::new(3));
     }
 
+    #[test]
+    fn test_eval_horner() {
+        let a = Poly::new(vec![FieldElement::new(3), FieldElement::new(0), FieldElement::new(2), FieldElement::new(1)]);
+        // 3 + 2 * 5^2 + 5^3
+        assert_eq!(a.eval(FieldElement::new(5)), FieldElement::new(178));
+        assert_eq!(Poly::zero().eval(FieldElement::new(5)), FieldElement::new(0));
+    }
+
+    #[test]
+    fn test_eval_many() {
+        let a = Poly::new((0..300).map(|i| FieldElement::new(i * i + 1)).collect());
+        let xs = (0..200).map(|i| FieldElement::new(3 * i + 11)).collect::<Vec<_>>();
+        let expected = xs.iter().map(|x| a.eval(*x)).collect::<Vec<_>>();
+        assert_eq!(a.eval_many(&xs), expected);
+        assert_eq!(a.multipoint_eval(&xs), expected);
+        assert_eq!(a.eval_many(&xs[..3]), expected[..3]);
+        assert!(a.eval_many(&[]).is_empty());
+    }
+
     #[test]
     fn test_mul() {
-        let a = Poly::new([FieldElement::new(1);256]);
+        let a = Poly::new(vec![FieldElement::new(1);256]);
         let b = a.mul(FieldElement::new(2));
         assert_eq!(b.coeffs[0], FieldElement::new(2));
     }
 
     #[test]
     fn test_mul_poly() {
-        let mut coeffs = [FieldElement::new(0); 256];
+        let mut coeffs = vec![FieldElement::new(0); 256];
         coeffs[2] = FieldElement::new(1);
         let a = Poly::new(coeffs);
-        let b = Poly::new([FieldElement::new(2);256]);
+        let b = Poly::new(vec![FieldElement::new(2);256]);
         let c = a.mul_poly(b);
         assert_eq!(c.coeffs[0], FieldElement::new(0));
         assert_eq!(c.coeffs[2], FieldElement::new(2));
+        // the product is not truncated to 256 coefficients
+        assert_eq!(c.degree(), 257);
+        assert_eq!(c.coeffs[257], FieldElement::new(2));
+    }
+
+    fn sample(len: usize, seed: u64) -> Poly {
+        Poly::new((0..len as u64).map(|i| FieldElement::new(i * i * seed + 7 * i + seed)).collect())
+    }
+
+    #[test]
+    fn test_mul_algorithms() {
+        for (la, lb) in [(40, 40), (100, 37), (33, 200), (300, 300), (700, 260), (1000, 5), (257, 1024)] {
+            let a = sample(la, 3);
+            let b = sample(lb, 11);
+            let expected = mul_schoolbook(&a.coeffs, &b.coeffs);
+            assert_eq!(mul_karatsuba(&a.coeffs, &b.coeffs), expected);
+            assert_eq!(mul_ntt(&a.coeffs, &b.coeffs), expected);
+            assert_eq!(a.mul_poly(b).coeffs, expected);
+        }
+    }
+
+    #[test]
+    fn test_square_and_pow() {
+        let a = sample(50, 5);
+        assert_eq!(a.square(), a.mul_poly(a.clone()));
+        assert_eq!(a.pow(0), Poly::new(vec![FieldElement::new(1)]));
+        assert_eq!(a.pow(1), a);
+        assert_eq!(a.pow(3), a.square().mul_poly(a.clone()));
+
+        let x_plus_1 = Poly::new(vec![FieldElement::new(1), FieldElement::new(1)]);
+        let p = x_plus_1.pow(10);
+        assert_eq!(p.degree(), 10);
+        assert_eq!(p.coeffs[5], FieldElement::new(252));
+    }
+
+    #[test]
+    fn test_div_rem_newton() {
+        let a = sample(1500, 3);
+        let b = sample(400, 7);
+        let (q, r) = a.div_rem(&b);
+        assert!(r.degree() < b.degree());
+        assert_eq!(q.mul_poly(b.clone()).add(r.clone()), a);
+
+        // same result as the schoolbook long division
+        assert_eq!(a.div_rem_schoolbook(&b), (q, r));
+    }
+
+    fn x() -> Poly {
+        Poly::new(vec![FieldElement::new(0), FieldElement::new(1)])
+    }
+
+    fn constant(c: u64) -> Poly {
+        Poly::new(vec![FieldElement::new(c)])
+    }
+
+    #[test]
+    fn test_operators() {
+        let a = constant(2) + x();
+        let b = &x() * &x() - constant(1);
+        assert_eq!(&a + &b, Poly::new(vec![FieldElement::new(1), FieldElement::new(1), FieldElement::new(1)]));
+        assert_eq!(a.clone() - &b, &a + &(-&b));
+        assert_eq!(&a * b.clone(), a.mul_poly(b.clone()));
+        assert_eq!(&a * FieldElement::new(3), constant(6) + x() * FieldElement::new(3));
+        assert_eq!(-&a + a.clone(), Poly::zero());
+        assert_eq!(-b, constant(1) - x() * x());
+    }
+
+    #[test]
+    fn test_compose() {
+        // p(x) = x^2 + 1, q(x) = x + 2, p(q(x)) = x^2 + 4x + 5
+        let p = x() * x() + constant(1);
+        let q = x() + constant(2);
+        assert_eq!(p.compose(&q), x() * x() + x() * FieldElement::new(4) + constant(5));
+        assert_eq!(p.compose(&q).eval(FieldElement::new(3)), p.eval(q.eval(FieldElement::new(3))));
+        assert_eq!(Poly::zero().compose(&q), Poly::zero());
+    }
+
+    #[test]
+    fn test_shift() {
+        // next row constraint on the trace domain: t(g x) - t(x)^2 vanishes
+        // on the first 3 points when the trace is [2, 4, 16, 256]
+        let g = FieldElement::nth_root_of_unity(4);
+        let t = Poly::new(Poly::inv_fft(&[FieldElement::new(2), FieldElement::new(4), FieldElement::new(16), FieldElement::new(256)], &g));
+        let constraint = t.shift(g) - &t * &t;
+        for i in 0..3 {
+            assert_eq!(constraint.eval(g.pow(i)), FieldElement::new(0));
+        }
+        assert_ne!(constraint.eval(g.pow(3)), FieldElement::new(0));
+
+        let y = FieldElement::new(12345);
+        assert_eq!(t.shift(g).eval(y), t.eval(g * y));
+    }
+
+    #[test]
+    fn test_derivative() {
+        // (3 + 2x + 5x^3)' = 2 + 15x^2
+        let p = constant(3) + x() * FieldElement::new(2) + x().pow(3) * FieldElement::new(5);
+        assert_eq!(p.derivative(), constant(2) + x().square() * FieldElement::new(15));
+        assert_eq!(constant(7).derivative(), Poly::zero());
+    }
+
+    #[test]
+    fn test_display() {
+        let p = constant(3) + x() + x().pow(3) * FieldElement::new(5);
+        assert_eq!(p.to_string(), "3 + x + 5x^3");
+        assert_eq!(x().square().to_string(), "x^2");
+        assert_eq!(Poly::zero().to_string(), "0");
+    }
+
+    #[test]
+    fn test_mul_poly_large() {
+        // (1 + x^300) * (1 - x^300) = 1 - x^600
+        let mut a = vec![FieldElement::new(0); 301];
+        a[0] = FieldElement::new(1);
+        a[300] = FieldElement::new(1);
+        let mut b = a.clone();
+        b[300] = -FieldElement::new(1);
+
+        let c = Poly::new(a).mul_poly(Poly::new(b));
+        assert_eq!(c.degree(), 600);
+        assert_eq!(c.coeffs[0], FieldElement::new(1));
+        assert_eq!(c.coeffs[300], FieldElement::new(0));
+        assert_eq!(c.coeffs[600], -FieldElement::new(1));
+    }
+
+    #[test]
+    fn test_div_rem() {
+        // (x^3 + 2x + 5) = (x^2 + x + 3) * (x - 1) + (8)
+        let a = Poly::new(vec![FieldElement::new(5), FieldElement::new(2), FieldElement::new(0), FieldElement::new(1)]);
+        let b = Poly::new(vec![-FieldElement::new(1), FieldElement::new(1)]);
+        let (q, r) = a.div_rem(&b);
+        assert_eq!(q, Poly::new(vec![FieldElement::new(3), FieldElement::new(1), FieldElement::new(1)]));
+        assert_eq!(r, Poly::new(vec![FieldElement::new(8)]));
+
+        // non monic divisor, a = q * c + r
+        let c = Poly::new(vec![FieldElement::new(3), FieldElement::new(0), FieldElement::new(7)]);
+        let (q, r) = a.div_rem(&c);
+        assert!(r.degree() < c.degree());
+        assert_eq!(q.mul_poly(c).add(r), a);
+
+        // smaller dividend
+        let (q, r) = b.div_rem(&a);
+        assert!(q.is_zero());
+        assert_eq!(r, b);
+    }
+
+    #[test]
+    fn test_div_by_vanishing() {
+        let q = Poly::new(vec![FieldElement::new(1), FieldElement::new(2), FieldElement::new(3), FieldElement::new(4), FieldElement::new(5)]);
+        let r = Poly::new(vec![FieldElement::new(9), FieldElement::new(8)]);
+        let z = Poly::vanishing(4);
+        let p = q.mul_poly(z.clone());
+        assert_eq!(p.div_by_vanishing(4), Some(q.clone()));
+        assert_eq!(p.div_rem(&z), (q.clone(), Poly::zero()));
+
+        let p = p.add(r.clone());
+        assert_eq!(p.div_rem_vanishing(4), (q, r));
+        assert_eq!(p.div_by_vanishing(4), None);
+    }
+
+    #[test]
+    fn test_div_by_vanishing_except() {
+        // a polynomial vanishing on every power of g but the last one
+        let g = FieldElement::nth_root_of_unity(8);
+        let last = g.pow(7);
+        let (z, _) = Poly::vanishing(8).div_rem(&Poly::new(vec![-last, FieldElement::new(1)]));
+        let q = Poly::new(vec![FieldElement::new(3), FieldElement::new(1), FieldElement::new(4)]);
+        let p = q.mul_poly(z);
+        assert!(p.div_by_vanishing(8).is_none());
+        assert_eq!(p.div_by_vanishing_except(8, &[last]), Some(q));
+    }
+
+    #[test]
+    fn test_eval_vanishing_on_coset() {
+        let offset = FieldElement::generator();
+        let root = FieldElement::nth_root_of_unity(32);
+        let z = Poly::vanishing(8);
+        let evals = Poly::eval_vanishing_on_coset(8, offset, 32);
+        for (i, y) in evals.iter().enumerate() {
+            assert_eq!(*y, z.eval(offset * root.pow(i as u64)));
+        }
+    }
+
+    #[test]
+    fn test_trim_and_degree() {
+        let p = Poly::new(vec![FieldElement::new(1), FieldElement::new(2), FieldElement::new(0), FieldElement::new(0)]);
+        assert_eq!(p.coeffs.len(), 2);
+        assert_eq!(p.degree(), 1);
+        assert_eq!(p.coeff(5), FieldElement::new(0));
+
+        let zero = Poly::new(vec![FieldElement::new(0); 4]);
+        assert!(zero.is_zero());
+        assert_eq!(zero, Poly::zero());
+
+        // cancelling leading terms lowers the degree
+        let a = Poly::new(vec![FieldElement::new(1), FieldElement::new(1)]);
+        let b = Poly::new(vec![FieldElement::new(2), FieldElement::new(1)]);
+        assert_eq!(a.sub(b), Poly::new(vec![-FieldElement::new(1)]));
     }
 
     #[test]
@@ -218,11 +895,7 @@ mod tests {
         let x = [FieldElement::new(0), FieldElement::new(1), FieldElement::new(2)];
         let y = [FieldElement::new(2), FieldElement::new(3), FieldElement::new(6)];
 
-        //let expected_coeffs = [FieldElement::new(2), FieldElement::new(0), FieldElement::new(1)];
-        let mut expected_coeffs = [FieldElement::new(0); 256];
-        expected_coeffs[0] = FieldElement::new(2);
-        expected_coeffs[1] = FieldElement::new(0);
-        expected_coeffs[2] = FieldElement::new(1);
+        let expected_coeffs = vec![FieldElement::new(2), FieldElement::new(0), FieldElement::new(1)];
 
 
         let p = Poly::lagrange_interpolation(&x, &y);
@@ -230,6 +903,47 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_lagrange_interpolation_arbitrary_points() {
+        let x = [FieldElement::new(5), FieldElement::new(P-3), FieldElement::new(42), FieldElement::new(1000)];
+        let y = [FieldElement::new(1), FieldElement::new(7), FieldElement::new(0), FieldElement::new(P-1)];
+
+        let p = Poly::lagrange_interpolation(&x, &y);
+        assert!(p.degree() < x.len());
+        for (xi, yi) in x.iter().zip(y.iter()) {
+            assert_eq!(p.eval(*xi), *yi);
+        }
+        assert_eq!(Poly::fast_interpolation(&x, &y), p);
+
+        // interpolating a polynomial recovers it
+        let q = Poly::new(vec![FieldElement::new(3), FieldElement::new(0), FieldElement::new(P-2)]);
+        let ys = x.iter().map(|xi| q.eval(*xi)).collect::<Vec<_>>();
+        assert_eq!(Poly::lagrange_interpolation(&x, &ys), q);
+    }
+
+    #[test]
+    fn test_lagrange_interpolation_roots_of_unity() {
+        // the same points FRI interpolates its rows on
+        let g = FieldElement::nth_root_of_unity(16);
+        let x = [g, g.pow(5), g.pow(9), g.pow(13)];
+        let y = [FieldElement::new(4), FieldElement::new(8), FieldElement::new(15), FieldElement::new(16)];
+        let p = Poly::lagrange_interpolation(&x, &y);
+        for (xi, yi) in x.iter().zip(y.iter()) {
+            assert_eq!(p.eval(*xi), *yi);
+        }
+    }
+
+    #[test]
+    fn test_fast_interpolation_large() {
+        let x = (0..100).map(|i| FieldElement::new(i * i + 3)).collect::<Vec<_>>();
+        let y = (0..100).map(|i| FieldElement::new(i * 7 + 1)).collect::<Vec<_>>();
+        let p = Poly::fast_interpolation(&x, &y);
+        assert!(p.degree() < 100);
+        for (xi, yi) in x.iter().zip(y.iter()) {
+            assert_eq!(p.eval(*xi), *yi);
+        }
+    }
+
     #[test]
     fn test_fft() {
         let mut coeffs = [FieldElement::new(0);8];
@@ -302,6 +1016,51 @@ mod tests {
         assert_eq!(arr[..len], arr2[..len2]);
     }
 
+    #[test]
+    fn test_fft_non_canonical_root() {
+        // the inverse root is not in the domain cache, the twiddles are computed on the fly
+        let coeffs = [FieldElement::new(3), FieldElement::new(1), FieldElement::new(4), FieldElement::new(1)];
+        let n = FieldElement::nth_root_of_unity(4).inv();
+        assert_eq!(Poly::fft(&coeffs, &n), Poly::naive_fft(&coeffs, &n));
+    }
+
+    #[test]
+    fn test_coset_fft() {
+        let coeffs = [FieldElement::new(3), FieldElement::new(1), FieldElement::new(4), FieldElement::new(1)];
+        let p = Poly::new(coeffs.to_vec());
+        let offset = FieldElement::generator();
+        let root = FieldElement::nth_root_of_unity(8);
+
+        // shorter coefficient vectors are zero padded to the domain size
+        let evals = Poly::coset_fft(&coeffs, offset, &root);
+        assert_eq!(evals.len(), 8);
+        for (i, y) in evals.iter().enumerate() {
+            assert_eq!(*y, p.eval(offset * root.pow(i as u64)));
+        }
+
+        let back = Poly::coset_inv_fft(&evals, offset, &root);
+        assert_eq!(Poly::new(back), p);
+    }
+
+    #[test]
+    fn test_low_degree_extend() {
+        let g = FieldElement::nth_root_of_unity(4);
+        let p = Poly::new(vec![FieldElement::new(5), FieldElement::new(0), FieldElement::new(2), FieldElement::new(P-1)]);
+        let evals = (0..4).map(|i| p.eval(g.pow(i))).collect::<Vec<_>>();
+
+        let offset = FieldElement::generator();
+        let lde = Poly::low_degree_extend(&evals, 4, offset);
+        assert_eq!(lde.len(), 16);
+
+        // the coset is disjoint from the trace domain and contains the extension of p
+        let root = FieldElement::nth_root_of_unity(16);
+        for (i, y) in lde.iter().enumerate() {
+            let x = offset * root.pow(i as u64);
+            assert_ne!(x.pow(4), FieldElement::new(1));
+            assert_eq!(*y, p.eval(x));
+        }
+    }
+
     #[test]
     fn test_inv_fft() {
 
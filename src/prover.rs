@@ -1,144 +1,867 @@
-//import the field
+// generic STARK prover for the computations described by an Air
+// the trace is low degree extended over a coset and committed, the constraints are combined into
+// the composition polynomial, and FRI proves the DEEP composition of both is low degree
+// in zk mode the trace is blinded, the composition segments are randomized and masked by a random
+// polynomial in the DEEP composition, and the leaves of the commitments are salted, so the
+// openings reveal nothing about the trace
+
+use crate::air::{Air, AirError, TransitionDomain};
+use crate::assertion::Assertion;
+use crate::domain::Domain;
 use crate::field::FieldElement;
-use crate::field::P;
+use crate::fri::{first_layer_queries, prove_low_degree, FRIComponent, NUM_QUERIES};
+use crate::lagrange::LagrangePoly;
+use crate::merkle_tree::{merkelize, mk_branch};
 use crate::poly::Poly;
-/*fn multiset_eq_rec(a: &[u64], b: &[u64], out_prev: &[u64] out: &mut [u64]) {
-    out = out_prev * a /b; 
-}
-
-fn multiset_eq(a: &[u64], b: &[u64]) -> bool {
-    let mut out = [0u64];
-    multiset_eq_rec(a, b, [1u64; 2], &mut out);
-    out[0] == 1 && out[1] == 1
-}*/
+use crate::transcript::Transcript;
+use rand_core::RngCore;
 
-fn is_a_power_of_2(x: u64) -> bool {
+pub fn is_a_power_of_2(x: u64) -> bool {
     x != 0 && (x & (x - 1)) == 0
 }
 
+#[allow(non_upper_case_globals)]
 pub const extension_factor: u64 = 8;
 
-fn generate_computational_trace(a: &[FieldElement], b: &[FieldElement], steps:u64) -> Vec<FieldElement> {
-    let mut computational_trace = vec![FieldElement::new(1)];
-    for i in 0..steps as usize {
-        computational_trace.push(
-            computational_trace[i] * a[i] / b[i]
-        );
+// random bytes appended to every leaf of the trace and composition commitments in zk mode, so a
+// merkle branch reveals nothing about the neighbouring rows
+pub const SALT_SIZE: usize = 16;
+
+// the blinded trace columns have steps random coefficients, so the openings are hiding as long as
+// fewer than steps evaluations of each column are revealed: the 4 positions of each FRI query, the
+// out of domain point z and g1 z
+pub const MIN_ZK_STEPS: usize = 4 * NUM_QUERIES + 3;
+
+// the trace columns have degree < steps, or < 2 steps once blinded in zk mode
+pub fn trace_degree_bound(steps: usize, zk: bool) -> usize {
+    if zk { 2 * steps } else { steps }
+}
+
+// the transcripts of zk proofs are separated from the others
+pub fn transcript_label(zk: bool) -> &'static [u8] {
+    if zk { b"stark-zk" } else { b"stark" }
+}
+
+// evaluations of the trace columns, main and auxiliary, at the out of domain point z and at g1 z,
+// and of the segments of the composition polynomial at z, followed in zk mode by the random
+// polynomial masking them
+#[derive(Clone, Debug)]
+pub struct OodEvaluations {
+    pub trace: Vec<FieldElement>,
+    pub trace_next: Vec<FieldElement>,
+    pub composition: Vec<FieldElement>,
+}
+
+#[derive(Debug)]
+pub struct StarkProof {
+    pub zk: bool,
+    pub trace_root: Vec<u8>,
+    // root of the auxiliary columns, if the air has any
+    pub aux_trace_root: Option<Vec<u8>>,
+    pub composition_root: Vec<u8>,
+    pub ood: OodEvaluations,
+    pub deep_root: Vec<u8>,
+    // merkle branches of the trace and of the composition segments at the positions queried by FRI
+    pub trace_branches: Vec<Vec<Vec<u8>>>,
+    pub aux_trace_branches: Vec<Vec<Vec<u8>>>,
+    pub composition_branches: Vec<Vec<Vec<u8>>>,
+    pub fri_proof: Vec<FRIComponent>,
+}
+
+// merkle leaf of row i of a multi column commitment, the concatenation of every column and of
+// the salt of the row, if any
+fn row_leaf(columns: &[Vec<FieldElement>], salts: &[Vec<u8>], i: usize) -> Vec<u8> {
+    let mut leaf = columns.iter().flat_map(|c| c[i].to_bytes()).collect::<Vec<_>>();
+    if let Some(salt) = salts.get(i) {
+        leaf.extend_from_slice(salt);
+    }
+    leaf
+}
+
+// commit to the low degree extended columns, one leaf per row
+// salts is empty, or holds one salt per row in zk mode
+pub fn commit_columns(columns: &[Vec<FieldElement>], salts: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    merkelize(&(0..columns[0].len()).map(|i| row_leaf(columns, salts, i)).collect::<Vec<_>>())
+}
+
+// random salts of SALT_SIZE bytes for the rows of a commitment, none without an rng
+pub fn random_salts(rng: Option<&mut (dyn RngCore + '_)>, rows: usize) -> Vec<Vec<u8>> {
+    match rng {
+        Some(rng) => (0..rows)
+            .map(|_| {
+                let mut salt = vec![0u8; SALT_SIZE];
+                rng.fill_bytes(&mut salt);
+                salt
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+// decode a leaf of a multi column commitment back into the row of num_columns values, dropping
+// the salt of salted leaves
+pub fn decode_row(leaf: &[u8], num_columns: usize, salted: bool) -> Option<Vec<FieldElement>> {
+    let salt = if salted { SALT_SIZE } else { 0 };
+    if leaf.len() != 8 * num_columns + salt {
+        return None;
+    }
+    FieldElement::batch_from_bytes(&leaf[..8 * num_columns].chunks(8).collect::<Vec<_>>()).ok()
+}
+
+// evaluations of the assertion quotients (T(x) - I(x)) / Z(x) over the coset offset * <root>
+// the assertions have been checked by check_air
+fn assertion_quotients(
+    assertions: &[Assertion],
+    trace_columns: &[Vec<FieldElement>],
+    steps: usize,
+    offset: FieldElement,
+    root: FieldElement
+) -> Vec<Vec<FieldElement>> {
+    assertions.iter()
+        .map(|assertion| {
+            let interpolant = Poly::coset_fft(&assertion.interpolant(steps).coeffs, offset, &root);
+            let vanishing = Poly::coset_fft(&assertion.vanishing_poly(steps).coeffs, offset, &root);
+            trace_columns[assertion.column()].iter()
+                .zip(interpolant.iter())
+                .zip(FieldElement::multi_inv(&vanishing).iter())
+                .map(|((t, i), inv_z)| (*t - *i) * *inv_z)
+                .collect()
+        })
+        .collect()
+}
+
+// the assertion quotients at the out of domain point z, from the trace columns at z
+// None if z is one of the asserted points
+pub fn assertion_quotients_at(
+    assertions: &[Assertion],
+    trace: &[FieldElement],
+    z: FieldElement,
+    steps: usize
+) -> Option<Vec<FieldElement>> {
+    assertions.iter()
+        .map(|assertion| {
+            let vanishing = assertion.eval_vanishing(z, steps);
+            if vanishing == FieldElement::new(0) {
+                return None;
+            }
+            Some((trace[assertion.column()] - assertion.eval_interpolant(z, steps)) / vanishing)
+        })
+        .collect()
+}
+
+// the constraint quotients at the out of domain point z, from the trace columns at z and g1 z
+// None if z is in the trace domain
+pub fn quotients_at<A: Air>(
+    air: &A,
+    ood: &OodEvaluations,
+    challenges: &[FieldElement],
+    z: FieldElement
+) -> Option<Vec<FieldElement>> {
+    let steps = air.steps();
+    if z.pow(steps as u64) == FieldElement::new(1) {
+        return None;
+    }
+
+    // each transition is divided by the polynomial vanishing on its domain
+    let periodic = air.periodic_columns().iter().map(|c| c.eval(z, steps)).collect::<Vec<_>>();
+    let mut quotients = air.evaluate_transition(&ood.trace, &ood.trace_next, &periodic, challenges).iter()
+        .zip(air.transition_domains().iter())
+        .map(|(n, domain)| *n / domain.eval_vanishing(z, steps))
+        .collect::<Vec<_>>();
+    quotients.extend(assertion_quotients_at(&air.assertions(), &ood.trace, z, steps)?);
+    Some(quotients)
+}
+
+// the composition polynomial has degree < segments * steps, and is committed as that many
+// segments of degree < steps, like the trace columns
+// enough segments for the largest quotient, e.g. d - 1 for a constraint of degree d on every step
+// but the last one
+pub fn num_segments(quotient_degrees: &[usize], steps: usize) -> usize {
+    let max_degree = quotient_degrees.iter().max().copied().unwrap_or(0);
+    (max_degree + 1).div_ceil(steps).max(1)
+}
+
+// number of committed segments, in zk mode at least 2 so that mask_segments can randomize them
+pub fn num_composition_segments<A: Air>(air: &A, zk: bool) -> usize {
+    let segments = num_segments(&quotient_degrees(air, zk), air.steps());
+    if zk { segments.max(2) } else { segments }
+}
+
+// degree the constraint quotients are lifted to in the composition polynomial
+pub fn composition_degree<A: Air>(air: &A, zk: bool) -> usize {
+    num_segments(&quotient_degrees(air, zk), air.steps()) * air.steps() - 1
+}
+
+// degrees of the constraint quotients, the transitions first and then the assertions
+// a transition of degree d over columns of degree < n has degree d (n - 1), and is divided by
+// the vanishing polynomial of its domain, with n = steps, or 2 steps in zk mode
+pub fn quotient_degrees<A: Air>(air: &A, zk: bool) -> Vec<usize> {
+    let steps = air.steps();
+    let n = trace_degree_bound(steps, zk);
+    let mut degrees = air.transition_degrees().iter()
+        .zip(air.transition_domains().iter())
+        .map(|(d, domain)| (d * (n - 1)).saturating_sub(domain.num_steps(steps)))
+        .collect::<Vec<_>>();
+    degrees.extend(air.assertions().iter().map(|a| (n - 1).saturating_sub(a.num_steps(steps))));
+    degrees
+}
+
+// smallest power of 2 blowup of the trace domain the composition polynomial fits in
+pub fn required_blowup<A: Air>(air: &A, zk: bool) -> usize {
+    num_segments(&quotient_degrees(air, zk), air.steps()).next_power_of_two()
+}
+
+// check the air can be proven and verified with the extension factor
+pub fn check_air<A: Air>(air: &A, zk: bool) -> Result<(), AirError> {
+    let steps = air.steps();
+    if !steps.is_power_of_two() || steps as u64 > 2u64.pow(32) / extension_factor {
+        return Err(AirError::InvalidSteps(steps));
+    }
+
+    // the expressions are evaluated below, so their indices must fit first
+    let width = air.num_columns() + air.num_aux_columns();
+    let expressions = air.transition_constraints();
+    if let Some(i) = expressions.iter().position(|e| !e.fits(width, air.periodic_columns().len(), air.num_challenges())) {
+        return Err(AirError::InvalidConstraintIndex(i));
+    }
+
+    // the constraints evaluated on a dummy row must match the stated number, or the extra ones
+    // would be silently dropped when zipped with their domains and degrees
+    let num_constraints = air.num_transition_constraints();
+    let zeros = |n: usize| vec![FieldElement::new(0); n];
+    let evaluated = air.evaluate_transition(&zeros(width), &zeros(width), &zeros(air.periodic_columns().len()), &zeros(air.num_challenges()));
+    if evaluated.len() != num_constraints {
+        return Err(AirError::WrongNumberOfTransitionConstraints(evaluated.len()));
+    }
+    if !expressions.is_empty() {
+        if expressions.len() != num_constraints {
+            return Err(AirError::WrongNumberOfTransitionConstraints(expressions.len()));
+        }
+        let degrees = air.transition_degrees();
+        if let Some(i) = expressions.iter().zip(degrees.iter()).position(|(e, d)| *d < e.degree()) {
+            return Err(AirError::UnderstatedTransitionDegree(i));
+        }
+    }
+
+    let transition_domains = air.transition_domains();
+    if transition_domains.len() != air.num_transition_constraints() {
+        return Err(AirError::WrongNumberOfTransitionDomains(transition_domains.len()));
+    }
+    if let Some(i) = transition_domains.iter().position(|d| !d.is_valid(steps)) {
+        return Err(AirError::InvalidTransitionDomain(i));
+    }
+    for assertion in air.assertions().iter() {
+        assertion.validate(steps).map_err(AirError::InvalidAssertion)?;
+        if assertion.column() >= width {
+            return Err(AirError::InvalidAssertionColumn(assertion.column()));
+        }
+    }
+    if let Some(i) = air.periodic_columns().iter().position(|c| !steps.is_multiple_of(c.period())) {
+        return Err(AirError::InvalidPeriodicColumn(i));
+    }
+    let required = required_blowup(air, zk);
+    if required > extension_factor as usize {
+        return Err(AirError::BlowupTooSmall { required, extension_factor: extension_factor as usize });
+    }
+    if zk && steps < MIN_ZK_STEPS {
+        return Err(AirError::TooFewStepsForZk { steps, required: MIN_ZK_STEPS });
+    }
+    Ok(())
+}
+
+// split the composition polynomial H, given by its evaluations over the coset offset * <g2>, into
+// segments H_i of degree < steps such that H(x) = sum x^(i steps) H_i(x)
+pub fn split_composition(evaluations: &[FieldElement], offset: FieldElement, steps: usize, segments: usize) -> Vec<Poly> {
+    let coeffs = LagrangePoly::new(evaluations.to_vec(), offset).to_poly().coeffs;
+    (0..segments)
+        .map(|i| Poly::new(coeffs.iter().skip(i * steps).take(steps).cloned().collect()))
+        .collect()
+}
+
+// randomize the segments without changing the polynomial they recombine to, for zk mode
+// with random r_i of degree < steps, H_i + x^steps r_i - r_(i-1) telescope in sum x^(i steps) H_i,
+// and have degree < 2 steps, the degree bound of the blinded trace
+// every segment gets a random part, so their openings reveal nothing about the H_i
+pub fn mask_segments(segments: &[Poly], steps: usize, mut rng: &mut dyn RngCore) -> Vec<Poly> {
+    let r = (0..segments.len() - 1)
+        .map(|_| (0..steps).map(|_| FieldElement::random(&mut rng)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    segments.iter()
+        .enumerate()
+        .map(|(i, h)| {
+            let mut coeffs = h.coeffs.clone();
+            coeffs.resize(2 * steps, FieldElement::new(0));
+            if let Some(r) = r.get(i) {
+                for (c, r) in coeffs[steps..].iter_mut().zip(r.iter()) {
+                    *c += *r;
+                }
+            }
+            if i > 0 {
+                for (c, r) in coeffs.iter_mut().zip(r[i - 1].iter()) {
+                    *c -= *r;
+                }
+            }
+            Poly::new(coeffs)
+        })
+        .collect()
+}
+
+// H(z) from the evaluations of its segments at z
+pub fn recombine_segments(segments: &[FieldElement], z: FieldElement, steps: usize) -> FieldElement {
+    let z_steps = z.pow(steps as u64);
+    segments.iter().rev().fold(FieldElement::new(0), |acc, h| acc * z_steps + *h)
+}
+
+// random coefficients (alpha_i, beta_i) of the composition, one pair per constraint
+pub fn composition_coefficients(transcript: &mut Transcript, num_constraints: usize) -> Vec<(FieldElement, FieldElement)> {
+    (0..num_constraints).map(|_| (transcript.challenge(), transcript.challenge())).collect()
+}
+
+// combine the constraint quotients at x into the composition polynomial
+// each quotient q_i of degree d_i is lifted to the target degree D as (alpha_i + beta_i x^(D - d_i)) q_i
+// with random alpha_i, beta_i, so that one low degree test on the sum bounds the degree of every
+// quotient: if any q_i has degree > d_i, the sum has degree > D except with negligible probability
+pub fn compose_at(
+    quotients: &[FieldElement],
+    degrees: &[usize],
+    target_degree: usize,
+    coefficients: &[(FieldElement, FieldElement)],
+    x: FieldElement
+) -> FieldElement {
+    quotients.iter()
+        .zip(degrees.iter())
+        .zip(coefficients.iter())
+        .map(|((q, degree), (alpha, beta))| {
+            assert!(*degree <= target_degree, "constraint degree exceeds the composition degree");
+            (*alpha + *beta * x.pow((target_degree - degree) as u64)) * *q
+        })
+        .sum()
+}
+
+// DEEP composition at x, from the trace columns T_j and the composition segments H_i at x:
+// sum gamma_j (T_j(x) - T_j(z)) / (x - z) + gamma'_j (T_j(x) - T_j(g1 z)) / (x - g1 z)
+//   + sum gamma_i (H_i(x) - H_i(z)) / (x - z)
+// which is a polynomial exactly when the out of domain evaluations are correct
+// coefficients holds the gamma_j, then the gamma'_j, then the gamma_i
+// in zk mode the random masking polynomial comes last among the H_i
+pub fn deep_at(
+    ood: &OodEvaluations,
+    trace: &[FieldElement],
+    composition: &[FieldElement],
+    inv_x_minus_z: FieldElement,
+    inv_x_minus_gz: FieldElement,
+    coefficients: &[FieldElement]
+) -> FieldElement {
+    let n = trace.len();
+    let mut o = FieldElement::new(0);
+    for (i, h) in composition.iter().enumerate() {
+        o += coefficients[2 * n + i] * (*h - ood.composition[i]) * inv_x_minus_z;
     }
-    computational_trace
+    for j in 0..n {
+        o += coefficients[j] * (trace[j] - ood.trace[j]) * inv_x_minus_z;
+        o += coefficients[n + j] * (trace[j] - ood.trace_next[j]) * inv_x_minus_gz;
+    }
+    o
+}
 
+
+// low degree extend the trace columns over the coset offset * <g2>
+pub fn extend_trace(trace: &[Vec<FieldElement>]) -> Vec<Vec<FieldElement>> {
+    trace.iter()
+        .map(|c| Poly::low_degree_extend(c, extension_factor as usize, FieldElement::generator()))
+        .collect()
 }
 
+// low degree extend the trace columns blinded as T(x) + (x^steps - 1) r(x), with r random of
+// degree < steps
+// the columns keep their values on the trace domain, while any steps of their evaluations
+// elsewhere are uniformly random
+pub fn blind_trace(trace: &[Vec<FieldElement>], mut rng: &mut dyn RngCore) -> Vec<Vec<FieldElement>> {
+    let steps = trace[0].len();
+    let g1 = Domain::root_of_unity(steps.trailing_zeros());
+    let g2 = Domain::root_of_unity((steps * extension_factor as usize).trailing_zeros());
+    trace.iter()
+        .map(|c| {
+            let mut coeffs = Poly::inv_fft(c, &g1);
+            coeffs.resize(2 * steps, FieldElement::new(0));
+            for i in 0..steps {
+                let r = FieldElement::random(&mut rng);
+                coeffs[i] -= r;
+                coeffs[steps + i] += r;
+            }
+            Poly::coset_fft(&coeffs, FieldElement::generator(), &g2)
+        })
+        .collect()
+}
 
-pub fn make_proof_multiset(a: &[FieldElement], b: &[FieldElement], steps:u64){
-    assert!(steps <= 2u64.pow(32) / extension_factor);
-    assert!(is_a_power_of_2(steps));
+// low degree extend the columns, blinded when given an rng
+fn extend_columns(columns: &[Vec<FieldElement>], rng: Option<&mut (dyn RngCore + '_)>) -> Vec<Vec<FieldElement>> {
+    match rng {
+        Some(rng) => blind_trace(columns, rng),
+        None => extend_trace(columns),
+    }
+}
 
-    let precision = steps * extension_factor;
+// combine the transition and boundary constraint quotients into the composition polynomial
+// over the coset, from the low degree extended trace columns, main and auxiliary
+// the random coefficients are drawn from the transcript, after the trace commitments
+pub fn composition_evaluations<A: Air>(
+    air: &A,
+    trace_columns: &[Vec<FieldElement>],
+    challenges: &[FieldElement],
+    zk: bool,
+    transcript: &mut Transcript
+) -> Vec<FieldElement> {
+    let steps = air.steps();
+    let precision = steps * extension_factor as usize;
 
     // Root of unity such that x^precision=1
-    let g2 = FieldElement::new(7).pow((P-1) / precision);
-
-    // Root of unity such that x^steps=1
-    let skips = precision / steps;
-    let g1= g2.pow(skips);
-
-    // Powers of the higher-order root of unity
-    let xs = FieldElement::get_power_cycle(g2);
-    let last_step_position = xs[((steps-1)*extension_factor) as usize];
-
-    // Generate the computational trace
-
-    let computational_trace = generate_computational_trace(a, b, steps);
-
-    let output = computational_trace.last().unwrap();
-    println!("Done generating computational trace");
-
-    // Interpolate the computational trace into a polynomial P, with each step
-    // along a successive power of G1
-    let computational_trace_polynomial = Poly::inv_fft(&computational_trace, &g1);
-    let p_evaluations = Poly::fft(&computational_trace_polynomial, &g2);
-    println!("Converted computational steps into a polynomial and low-degree extended it");
-
-    // construct lagrange interpolation polynomials for A and B sets
-    let a_polynomial = Poly::inv_fft(&a.to_vec(), &g1);
-    let a_evaluations = Poly::fft(&a_polynomial, &g2);
-
-    let b_polynomial = Poly::inv_fft(&b.to_vec(), &g1);
-    let b_evaluations = Poly::fft(&b_polynomial, &g2);
-
-    // access p_evaluations as vec at position [(i+extension_factor)%precision]
-
-
-    // Create the composed polynomial such that
-    // C(P(x), P(g1*x), K(x)) = P(g1*x) - P(x) / A(x) * B(x) mod P
-    let c_of_p_evaluations: Vec<_> = (0..precision)
-    .map(|i| (p_evaluations[((i+extension_factor)%precision) as usize] -
-            p_evaluations[i as usize]
-            / a_evaluations[i as usize]
-            * b_evaluations[i as usize])
-            )
-    .collect();
-    println!("Computed C(P, K) polynomial");
-
-    // Compute interpolant of ((1, input), (x_atlast_step, output))
-    let interpolant =Poly::lagrange_interpolation(&[FieldElement::new(1), last_step_position], &[FieldElement::new(1), FieldElement::new(1)]);
-    let i_evaluations = xs.iter().map(|x| Poly::eval(&interpolant, *x)).collect::<Vec<_>>();
-
-    let mut a1 = Poly::new([(FieldElement::new(0)); 256]);
-    a1.coeffs[0] = FieldElement::new(P-1);
-    a1.coeffs[1] = FieldElement::new(1);
-    let mut a2 = Poly::new([(FieldElement::new(0)); 256]);
-    a2.coeffs[0] = -last_step_position;
-    a2.coeffs[1] = FieldElement::new(1);
-    let zeropoly2 = a1.mul_poly(a2);
-    let z2_evaluations = xs.iter().map(|x| Poly::eval(&zeropoly2, *x)).collect::<Vec<_>>();
-    let inv_z2_evaluations =  FieldElement::multi_inv(&z2_evaluations);
-
-    // B = (P - I) / Z2
-    let b_evaluations = p_evaluations.iter()
-        .zip(i_evaluations.iter())
-        .zip(inv_z2_evaluations.iter())
-        .map(|((p, i), invq)| ((*p - *i) * *invq))
+    let domain = Domain::new(precision.trailing_zeros());
+
+    // The evaluation domain is the coset offset * <g2>, which is disjoint from the
+    // trace domain <g1>, so the constraint quotients never divide by zero
+    let offset = FieldElement::generator();
+    let xs = domain.powers().iter().map(|x| offset * *x).collect::<Vec<_>>();
+
+    let assertions = air.assertions();
+    let coefficients = composition_coefficients(transcript, air.num_transition_constraints() + assertions.len());
+
+    // 1 / Z(x) for each distinct transition domain, the constraints index into them
+    let transition_domains = air.transition_domains();
+    let mut distinct_domains: Vec<TransitionDomain> = vec![];
+    let domain_indices = transition_domains.iter()
+        .map(|d| match distinct_domains.iter().position(|e| e == d) {
+            Some(j) => j,
+            None => {
+                distinct_domains.push(*d);
+                distinct_domains.len() - 1
+            }
+        })
+        .collect::<Vec<_>>();
+    let inv_z_evaluations = distinct_domains.iter()
+        .map(|d| d.inv_vanishing_on_coset(steps, precision, offset))
+        .collect::<Vec<_>>();
+
+    // the periodic columns repeat over the coset, with a period of their length times the blowup
+    let periodic_columns = air.periodic_columns().iter()
+        .map(|c| c.evaluate_on_coset(steps, precision, offset))
         .collect::<Vec<_>>();
 
-    println!("Computed B polynomial");
+    // the boundary constraints (T(x) - I(x)) / Z_a(x) of the assertions
+    let boundary_evaluations = assertion_quotients(&assertions, trace_columns, steps, offset, domain.generator());
+
+    // access the next step at position [(i+extension_factor)%precision]
+    // the transition quotients C(T(x), T(g1*x), K(x)) / Z(x) and the boundary quotients, combined into
+    // the composition polynomial
+    let degrees = quotient_degrees(air, zk);
+    let target_degree = composition_degree(air, zk);
+    (0..precision)
+        .map(|i| {
+            let next = (i + extension_factor as usize) % precision;
+            let current = trace_columns.iter().map(|c| c[i]).collect::<Vec<_>>();
+            let next = trace_columns.iter().map(|c| c[next]).collect::<Vec<_>>();
+            let periodic = periodic_columns.iter().map(|c| c[i % c.len()]).collect::<Vec<_>>();
+            let mut quotients = air.evaluate_transition(&current, &next, &periodic, challenges).iter()
+                .zip(domain_indices.iter())
+                .map(|(n, j)| *n * inv_z_evaluations[*j][i])
+                .collect::<Vec<_>>();
+            quotients.extend(boundary_evaluations.iter().map(|b| b[i]));
+            compose_at(&quotients, &degrees, target_degree, &coefficients, xs[i])
+        })
+        .collect()
+}
 
+// prove the trace, given as columns of air.steps() values, satisfies the air
+// fails if the air can't be proven, or the trace or its auxiliary columns don't fit it
+pub fn prove<A: Air>(air: &A, trace: &[Vec<FieldElement>]) -> Result<StarkProof, AirError> {
+    prove_with_rng(air, trace, None)
+}
 
-    
-    
+// zero knowledge proof that the trace satisfies the air, blinded and salted with randomness from rng
+// fails below MIN_ZK_STEPS steps, where the openings would reveal the trace
+pub fn prove_zk<A: Air>(air: &A, trace: &[Vec<FieldElement>], rng: &mut impl RngCore) -> Result<StarkProof, AirError> {
+    prove_with_rng(air, trace, Some(rng))
+}
+
+fn prove_with_rng<A: Air>(air: &A, trace: &[Vec<FieldElement>], mut rng: Option<&mut (dyn RngCore + '_)>) -> Result<StarkProof, AirError> {
+    let steps = air.steps();
+    let zk = rng.is_some();
+    check_air(air, zk)?;
+    if trace.len() != air.num_columns() {
+        return Err(AirError::WrongNumberOfColumns(trace.len()));
+    }
+    if let Some(i) = trace.iter().position(|c| c.len() != steps) {
+        return Err(AirError::WrongColumnLength(i));
+    }
+
+    let precision = steps * extension_factor as usize;
+    let domain = Domain::new(precision.trailing_zeros());
+    let g1 = Domain::root_of_unity(steps.trailing_zeros());
+    let offset = FieldElement::generator();
+    let xs = domain.powers().iter().map(|x| offset * *x).collect::<Vec<_>>();
+
+    // Interpolate the trace columns into polynomials, with each step along a successive
+    // power of G1, and evaluate them on the coset
+    let main_columns = extend_columns(trace, rng.as_deref_mut());
+
+    // commit to the trace, the challenges of the auxiliary segment are drawn from the root
+    let mut transcript = Transcript::new(transcript_label(zk));
+    let trace_tree = commit_columns(&main_columns, &random_salts(rng.as_deref_mut(), precision));
+    transcript.append(&trace_tree[1]);
+    let challenges = transcript.challenges(air.num_challenges());
+
+    // build, extend and commit to the auxiliary columns, the coefficients of the composition
+    // are drawn after both commitments
+    let aux_trace = air.build_aux_trace(trace, &challenges);
+    if aux_trace.len() != air.num_aux_columns() {
+        return Err(AirError::WrongNumberOfAuxColumns(aux_trace.len()));
+    }
+    if let Some(i) = aux_trace.iter().position(|c| c.len() != steps) {
+        return Err(AirError::WrongColumnLength(trace.len() + i));
+    }
+    let aux_columns = if aux_trace.is_empty() { vec![] } else { extend_columns(&aux_trace, rng.as_deref_mut()) };
+    let aux_tree = if aux_columns.is_empty() {
+        None
+    } else {
+        Some(commit_columns(&aux_columns, &random_salts(rng.as_deref_mut(), precision)))
+    };
+    if let Some(aux_tree) = &aux_tree {
+        transcript.append(&aux_tree[1]);
+    }
+    let trace_columns = [main_columns, aux_columns].concat();
+    let composition = composition_evaluations(air, &trace_columns, &challenges, zk, &mut transcript);
+
+    // split the composition polynomial into segments of degree < steps, and commit to them
+    // together, one leaf per row like the trace
+    // in zk mode the segments are randomized, and a random polynomial of the degree of the blinded
+    // trace is committed with them, and masks them in the DEEP composition
+    let mut segments = split_composition(&composition, offset, steps, num_composition_segments(air, zk));
+    if let Some(mut rng) = rng.as_deref_mut() {
+        segments = mask_segments(&segments, steps, rng);
+        segments.push(Poly::new((0..trace_degree_bound(steps, zk)).map(|_| FieldElement::random(&mut rng)).collect()));
+    }
+    let segment_evaluations = segments.iter()
+        .map(|h| Poly::coset_fft(&h.coeffs, offset, &domain.generator()))
+        .collect::<Vec<_>>();
+    let composition_tree = commit_columns(&segment_evaluations, &random_salts(rng, precision));
+    transcript.append(&composition_tree[1]);
+
+    // out of domain sampling: evaluate the trace at z and g1 z, and the composition segments at z
+    // so the verifier can check the constraints at z alone
+    let z = transcript.challenge();
+    let ood = OodEvaluations {
+        trace: trace_columns.iter().map(|c| LagrangePoly::new(c.clone(), offset).eval(z)).collect(),
+        trace_next: trace_columns.iter().map(|c| LagrangePoly::new(c.clone(), offset).eval(g1 * z)).collect(),
+        composition: segments.iter().map(|h| h.eval(z)).collect(),
+    };
+    transcript.append_field_elements(&ood.trace);
+    transcript.append_field_elements(&ood.trace_next);
+    transcript.append_field_elements(&ood.composition);
+
+    // DEEP composition over the coset, the polynomial we prove the degree of with FRI
+    let deep_coefficients = transcript.challenges(2 * trace_columns.len() + segments.len());
+    let inv_x_minus_z = FieldElement::multi_inv(&xs.iter().map(|x| *x - z).collect::<Vec<_>>());
+    let inv_x_minus_gz = FieldElement::multi_inv(&xs.iter().map(|x| *x - g1 * z).collect::<Vec<_>>());
+    let deep_evaluations = (0..precision)
+        .map(|i| deep_at(
+            &ood,
+            &trace_columns.iter().map(|c| c[i]).collect::<Vec<_>>(),
+            &segment_evaluations.iter().map(|h| h[i]).collect::<Vec<_>>(),
+            inv_x_minus_z[i],
+            inv_x_minus_gz[i],
+            &deep_coefficients
+        ))
+        .collect::<Vec<_>>();
+    let deep_tree = merkelize(&FieldElement::batch_to_bytes(&deep_evaluations));
+    transcript.append(&deep_tree[1]);
+
+    // the trace columns and the segments have degree < steps, or < 2 steps in zk mode, and the
+    // quotients by x - z and x - g1 z lower the degree by one
+    let fri_proof = prove_low_degree(
+        deep_evaluations,
+        domain.generator(),
+        deep_tree[1].clone(),
+        trace_degree_bound(steps, zk) as u128,
+        0
+    );
+
+    // open the trace and the composition where FRI opens the DEEP composition
+    let positions = first_layer_queries(&fri_proof, precision, 0)
+        .iter()
+        .map(|(position, _)| *position)
+        .collect::<Vec<_>>();
+
+    Ok(StarkProof {
+        zk,
+        trace_root: trace_tree[1].clone(),
+        aux_trace_root: aux_tree.as_ref().map(|t| t[1].clone()),
+        composition_root: composition_tree[1].clone(),
+        ood,
+        deep_root: deep_tree[1].clone(),
+        trace_branches: positions.iter().map(|p| mk_branch(&trace_tree, *p)).collect(),
+        aux_trace_branches: match &aux_tree {
+            Some(aux_tree) => positions.iter().map(|p| mk_branch(aux_tree, *p)).collect(),
+            None => vec![],
+        },
+        composition_branches: positions.iter().map(|p| mk_branch(&composition_tree, *p)).collect(),
+        fri_proof,
+    })
 }
 
 //tests
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::expression::Expr;
+    use rand_core::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
 
     #[test]
     fn test_is_a_power_of_two(){
-        assert_eq!(is_a_power_of_2(8), true);
-        assert_eq!(is_a_power_of_2(7), false);
+        assert!(is_a_power_of_2(8));
+        assert!(!is_a_power_of_2(7));
     }
 
-    
     #[test]
-    fn test_multiset_computational_trace() {
-        let a = [FieldElement::new(1), FieldElement::new(2), FieldElement::new(4), FieldElement::new(3)];
-        let b = [FieldElement::new(4), FieldElement::new(2), FieldElement::new(3), FieldElement::new(1)];
-        let steps = 4;
+    fn test_extend_trace() {
+        let steps = 8;
+        let trace = vec![(0..steps).map(|i| FieldElement::new(i * 7 + 1)).collect::<Vec<_>>()];
+        let extended = extend_trace(&trace);
+        assert_eq!(extended[0].len(), (steps * extension_factor) as usize);
+
+        // the evaluation domain is the coset offset * <g2>, where the trace interpolant is
+        // evaluated and the vanishing polynomial of the trace domain never is zero
+        let g1 = Domain::root_of_unity(steps.trailing_zeros());
+        let g2 = Domain::root_of_unity((steps * extension_factor).trailing_zeros());
+        let p = Poly::new(Poly::inv_fft(&trace[0], &g1));
+        for (i, y) in extended[0].iter().enumerate() {
+            let x = FieldElement::generator() * g2.pow(i as u64);
+            assert_ne!(x.pow(steps), FieldElement::new(1));
+            assert_eq!(*y, p.eval(x));
+        }
+    }
 
-        let trace = generate_computational_trace(&a, &b, steps);
-        
-        assert_eq!(*trace.last().unwrap(), FieldElement::new(1));
+    #[test]
+    fn test_split_composition() {
+        // a composition of degree < 3 steps, as given by constraints of degree 4
+        let steps = 8;
+        let offset = FieldElement::generator();
+        let h = Poly::new((0..3 * steps as u64).map(|i| FieldElement::new(i * i + 11)).collect());
+        let evaluations = LagrangePoly::from_poly(&h, steps * 8, offset).evals;
+
+        let segments = split_composition(&evaluations, offset, steps, 3);
+        assert_eq!(segments.len(), 3);
+        assert!(segments.iter().all(|s| s.degree() < steps));
+        let z = FieldElement::new(1234567);
+        let at_z = segments.iter().map(|s| s.eval(z)).collect::<Vec<_>>();
+        assert_eq!(recombine_segments(&at_z, z, steps), h.eval(z));
     }
 
+    #[test]
+    fn test_blind_trace() {
+        let steps = 16;
+        let trace = vec![(0..steps as u64).map(|i| FieldElement::new(i * i + 3)).collect::<Vec<_>>()];
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let blinded = blind_trace(&trace, &mut rng);
+        let other = blind_trace(&trace, &mut rng);
+        assert_ne!(blinded, other);
+
+        // the blinded column has degree < 2 steps and keeps the values of the trace
+        let offset = FieldElement::generator();
+        let p = LagrangePoly::new(blinded[0].clone(), offset).to_poly();
+        assert!(p.degree() < trace_degree_bound(steps, true));
+        assert!(p.degree() >= steps);
+        let g1 = Domain::root_of_unity(steps.trailing_zeros());
+        for (i, value) in trace[0].iter().enumerate() {
+            assert_eq!(p.eval(g1.pow(i as u64)), *value);
+        }
+    }
 
-}
+    #[test]
+    fn test_salted_rows() {
+        let columns = vec![vec![FieldElement::new(1), FieldElement::new(2)], vec![FieldElement::new(3), FieldElement::new(4)]];
+        let salts = random_salts(Some(&mut ChaCha20Rng::seed_from_u64(1)), 2);
+        let tree = commit_columns(&columns, &salts);
+        assert_ne!(tree[1], commit_columns(&columns, &[])[1]);
+        let leaf = &mk_branch(&tree, 1)[0];
+        assert_eq!(leaf.len(), 16 + SALT_SIZE);
+        assert_eq!(decode_row(leaf, 2, true), Some(vec![FieldElement::new(2), FieldElement::new(4)]));
+        assert_eq!(decode_row(leaf, 2, false), None);
+    }
 
+    #[test]
+    fn test_mask_segments() {
+        let steps = 8;
+        let mut rng = ChaCha20Rng::seed_from_u64(5);
+        let segments = (0..3)
+            .map(|i| Poly::new((0..steps as u64).map(|j| FieldElement::new(i * 100 + j)).collect()))
+            .collect::<Vec<_>>();
+        let masked = mask_segments(&segments, steps, &mut rng);
+        assert_eq!(masked.len(), 3);
+
+        // the masked segments recombine to the same polynomial, and every one of them is randomized
+        let z = FieldElement::new(31337);
+        let at_z = |s: &[Poly]| s.iter().map(|h| h.eval(z)).collect::<Vec<_>>();
+        assert_eq!(recombine_segments(&at_z(&masked), z, steps), recombine_segments(&at_z(&segments), z, steps));
+        for (h, m) in segments.iter().zip(masked.iter()) {
+            assert_ne!(h, m);
+            assert!(m.degree() < trace_degree_bound(steps, true));
+        }
+    }
 
+    #[test]
+    fn test_num_segments() {
+        let steps = 16;
+        assert_eq!(num_segments(&[], steps), 1);
+        assert_eq!(num_segments(&[0, 14], steps), 1);
+        assert_eq!(num_segments(&[15], steps), 1);
+        assert_eq!(num_segments(&[16, 3], steps), 2);
+        // degree 3 on every step but the last one, and degree 2 on every 4th step
+        assert_eq!(num_segments(&[3 * 15 - 15], steps), 2);
+        assert_eq!(num_segments(&[2 * 15 - 4], steps), 2);
+    }
 
+    // x_(i+1) = x_i^degree, with the first value asserted
+    struct PowerAir {
+        steps: usize,
+        degree: u64,
+    }
+
+    impl Air for PowerAir {
+        fn steps(&self) -> usize {
+            self.steps
+        }
 
+        fn num_columns(&self) -> usize {
+            1
+        }
 
+        fn transition_constraints(&self) -> Vec<Expr> {
+            vec![Expr::next(0) - Expr::current(0).pow(self.degree)]
+        }
 
+        fn assertions(&self) -> Vec<Assertion> {
+            vec![Assertion::single(0, 0, FieldElement::new(2))]
+        }
+    }
 
+    #[test]
+    fn test_check_air() {
+        // a constraint of degree d needs d - 1 segments, or 2 d - 1 in zk mode
+        let air = PowerAir { steps: 16, degree: 9 };
+        assert_eq!(air.transition_degrees(), vec![9]);
+        assert_eq!(required_blowup(&air, false), 8);
+        assert_eq!(check_air(&air, false), Ok(()));
+        assert_eq!(required_blowup(&air, true), 32);
+        assert_eq!(check_air(&air, true), Err(AirError::BlowupTooSmall { required: 32, extension_factor: 8 }));
+
+        let air = PowerAir { steps: 16, degree: 10 };
+        let err = check_air(&air, false).unwrap_err();
+        assert_eq!(err, AirError::BlowupTooSmall { required: 16, extension_factor: 8 });
+        assert_eq!(err.to_string(), "the constraints need a blowup factor of 16, but the extension factor is 8");
+
+        assert_eq!(check_air(&PowerAir { steps: 12, degree: 2 }, false), Err(AirError::InvalidSteps(12)));
+
+        // the openings of a zk proof are only hiding for more than 4 * 40 + 2 steps
+        assert_eq!(check_air(&PowerAir { steps: 128, degree: 2 }, true), Err(AirError::TooFewStepsForZk { steps: 128, required: 163 }));
+        assert_eq!(check_air(&PowerAir { steps: 256, degree: 2 }, true), Ok(()));
+    }
+
+    // x_(i+1) = x_i^2, evaluated by hand, with the degrees left to their default or understated
+    struct HandwrittenAir {
+        stated_degree: Option<usize>,
+    }
+
+    impl Air for HandwrittenAir {
+        fn steps(&self) -> usize {
+            16
+        }
+
+        fn num_columns(&self) -> usize {
+            1
+        }
+
+        fn transition_constraints(&self) -> Vec<Expr> {
+            match self.stated_degree {
+                Some(_) => vec![Expr::next(0) - Expr::current(0).pow(2)],
+                None => vec![],
+            }
+        }
+
+        fn transition_degrees(&self) -> Vec<usize> {
+            self.stated_degree.into_iter().collect()
+        }
+
+        fn evaluate_transition(&self, current: &[FieldElement], next: &[FieldElement], _periodic: &[FieldElement], _challenges: &[FieldElement]) -> Vec<FieldElement> {
+            vec![next[0] - current[0] * current[0]]
+        }
+
+        fn assertions(&self) -> Vec<Assertion> {
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_check_air_constraints() {
+        // without stated degrees, the constraint would be dropped and any trace would verify
+        let air = HandwrittenAir { stated_degree: None };
+        assert_eq!(check_air(&air, false), Err(AirError::WrongNumberOfTransitionConstraints(1)));
+        assert_eq!(check_air(&HandwrittenAir { stated_degree: Some(1) }, false), Err(AirError::UnderstatedTransitionDegree(0)));
+        assert_eq!(check_air(&HandwrittenAir { stated_degree: Some(2) }, false), Ok(()));
+    }
 
+    // a constraint and an assertion on indices that may not exist
+    struct OutOfBoundsAir {
+        constraint: Expr,
+        assertion_column: usize,
+    }
+
+    impl Air for OutOfBoundsAir {
+        fn steps(&self) -> usize {
+            16
+        }
+
+        fn num_columns(&self) -> usize {
+            1
+        }
+
+        fn transition_constraints(&self) -> Vec<Expr> {
+            vec![self.constraint.clone()]
+        }
+
+        fn assertions(&self) -> Vec<Assertion> {
+            vec![Assertion::single(self.assertion_column, 0, FieldElement::new(2))]
+        }
+    }
+
+    #[test]
+    fn test_check_air_indices() {
+        let air = |constraint: Expr, assertion_column| OutOfBoundsAir { constraint, assertion_column };
+        let x = || Expr::next(0) - Expr::current(0).pow(2);
+        assert_eq!(check_air(&air(x(), 0), false), Ok(()));
+        for e in [Expr::current(1), Expr::next(1), Expr::periodic(0), Expr::challenge(0)] {
+            assert_eq!(check_air(&air(x() + e, 0), false), Err(AirError::InvalidConstraintIndex(0)));
+        }
+        assert_eq!(check_air(&air(x(), 1), false), Err(AirError::InvalidAssertionColumn(1)));
+
+        // prove refuses them rather than panicking
+        let column = vec![FieldElement::new(2); 16];
+        assert_eq!(prove(&air(x(), 1), std::slice::from_ref(&column)).unwrap_err(), AirError::InvalidAssertionColumn(1));
+        assert_eq!(prove(&air(x() + Expr::current(1), 0), &[column]).unwrap_err(), AirError::InvalidConstraintIndex(0));
+    }
+
+    #[test]
+    fn test_prove_wrong_trace() {
+        let air = PowerAir { steps: 16, degree: 2 };
+        let column = vec![FieldElement::new(2); 16];
+        assert_eq!(prove(&air, &[column.clone(), column.clone()]).unwrap_err(), AirError::WrongNumberOfColumns(2));
+        assert_eq!(prove(&air, &[column[..8].to_vec()]).unwrap_err(), AirError::WrongColumnLength(0));
+    }
+
+    #[test]
+    fn test_prove_blowup_too_small() {
+        let air = PowerAir { steps: 16, degree: 10 };
+        let err = prove(&air, &[vec![FieldElement::new(2); 16]]).unwrap_err();
+        assert_eq!(err, AirError::BlowupTooSmall { required: 16, extension_factor: 8 });
+    }
+}
@@ -0,0 +1,142 @@
+// subproduct tree over a set of points, for fast interpolation and multipoint evaluation
+// https://cr.yp.to/arith/scaledmod-20040820.pdf, section 2
+// every level of the tree costs as much as multiplying and dividing polynomials of total degree n:
+// Poly uses the NTT and a Newton division for operands of 256 coefficients or more, so the top
+// levels cost O(n log n) each, while the levels below are schoolbook or Karatsuba
+// interpolating or evaluating n points thus costs O(n log^2 n) plus O(n * 256) for the small nodes
+
+use crate::field::FieldElement;
+use crate::poly::Poly;
+
+// each node holds the product of (x - x_i) over the points below it
+pub struct SubproductTree {
+    product: Poly,
+    children: Option<Box<(SubproductTree, SubproductTree)>>,
+}
+
+impl SubproductTree {
+    pub fn new(points: &[FieldElement]) -> SubproductTree {
+        assert!(!points.is_empty(), "a subproduct tree needs at least one point");
+        if points.len() == 1 {
+            return SubproductTree {
+                product: Poly::new(vec![-points[0], FieldElement::new(1)]),
+                children: None,
+            };
+        }
+
+        let (l, r) = points.split_at(points.len() / 2);
+        let left = SubproductTree::new(l);
+        let right = SubproductTree::new(r);
+        SubproductTree {
+            product: left.product.mul_poly(right.product.clone()),
+            children: Some(Box::new((left, right))),
+        }
+    }
+
+    // the product of (x - x_i) over all the points, i.e. their vanishing polynomial
+    pub fn product(&self) -> &Poly {
+        &self.product
+    }
+
+    // a tree always has at least one point
+    pub fn num_points(&self) -> usize {
+        self.product.degree()
+    }
+
+    // evaluate p at every point, in the order the points were given
+    // p mod (x - x_i) = p(x_i), and reducing modulo the subproducts on the way down
+    // keeps the degrees small
+    pub fn eval(&self, p: &Poly) -> Vec<FieldElement> {
+        let mut o = Vec::with_capacity(self.num_points());
+        self.eval_into(p, &mut o);
+        o
+    }
+
+    fn eval_into(&self, p: &Poly, o: &mut Vec<FieldElement>) {
+        let (_, r) = p.div_rem(&self.product);
+        match &self.children {
+            None => o.push(r.coeff(0)),
+            Some(children) => {
+                children.0.eval_into(&r, o);
+                children.1.eval_into(&r, o);
+            }
+        }
+    }
+
+    // interpolate the polynomial of degree < n through (x_i, y_i), the points must be distinct
+    // p(x) = sum y_i / M'(x_i) * M(x) / (x - x_i) where M is the product of the tree
+    pub fn interpolate(&self, ys: &[FieldElement]) -> Poly {
+        assert_eq!(ys.len(), self.num_points());
+        let denominators = self.eval(&self.product.derivative());
+        assert!(!denominators.contains(&FieldElement::new(0)), "interpolation points must be distinct");
+        let weights = FieldElement::multi_inv(&denominators)
+            .iter()
+            .zip(ys.iter())
+            .map(|(d, y)| *d * *y)
+            .collect::<Vec<_>>();
+        self.linear_combination(&weights)
+    }
+
+    // sum c_i * M(x) / (x - x_i), computed bottom up
+    fn linear_combination(&self, c: &[FieldElement]) -> Poly {
+        match &self.children {
+            None => Poly::new(vec![c[0]]),
+            Some(children) => {
+                let (left, right) = children.as_ref();
+                let (cl, cr) = c.split_at(left.num_points());
+                let l = left.linear_combination(cl);
+                let r = right.linear_combination(cr);
+                l.mul_poly(right.product.clone()).add(r.mul_poly(left.product.clone()))
+            }
+        }
+    }
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> Vec<FieldElement> {
+        [3, 17, 5, 1000, 2, 99, 42].iter().map(|x| FieldElement::new(*x)).collect()
+    }
+
+    #[test]
+    fn test_product() {
+        let xs = points();
+        let tree = SubproductTree::new(&xs);
+        assert_eq!(tree.num_points(), xs.len());
+        for x in xs {
+            assert_eq!(tree.product().eval(x), FieldElement::new(0));
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        let p = Poly::new((1..20).map(FieldElement::new).collect());
+        let xs = points();
+        let tree = SubproductTree::new(&xs);
+        let expected = xs.iter().map(|x| p.eval(*x)).collect::<Vec<_>>();
+        assert_eq!(tree.eval(&p), expected);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let xs = points();
+        let ys = [8, 1, 0, 6, 2, 2, 9].iter().map(|y| FieldElement::new(*y)).collect::<Vec<_>>();
+        let p = SubproductTree::new(&xs).interpolate(&ys);
+        assert!(p.degree() < xs.len());
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            assert_eq!(p.eval(*x), *y);
+        }
+        assert_eq!(p, Poly::lagrange_interpolation(&xs, &ys));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_interpolate_duplicate_points() {
+        let xs = [FieldElement::new(1), FieldElement::new(2), FieldElement::new(1)];
+        let ys = [FieldElement::new(1), FieldElement::new(2), FieldElement::new(3)];
+        SubproductTree::new(&xs).interpolate(&ys);
+    }
+}
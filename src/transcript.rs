@@ -0,0 +1,82 @@
+// Fiat-Shamir transcript: the prover and the verifier absorb the same commitments in the
+// same order, and draw the same pseudorandom challenges from them
+// https://en.wikipedia.org/wiki/Fiat%E2%80%93Shamir_heuristic
+
+use crate::field::{FieldElement, P};
+use crate::merkle_tree::hash_sha;
+
+#[derive(Clone, Debug)]
+pub struct Transcript {
+    state: Vec<u8>,
+    counter: u64,
+}
+
+impl Transcript {
+    // the label separates the transcripts of different protocols
+    pub fn new(label: &[u8]) -> Transcript {
+        Transcript { state: hash_sha(label.to_vec()), counter: 0 }
+    }
+
+    // absorb data, e.g. a Merkle root, into the state
+    pub fn append(&mut self, data: &[u8]) {
+        self.state = hash_sha([self.state.as_slice(), data].concat());
+        self.counter = 0;
+    }
+
+    pub fn append_field_elements(&mut self, values: &[FieldElement]) {
+        self.append(&FieldElement::batch_to_bytes(values).concat());
+    }
+
+    // next 32 pseudorandom bytes, hash(state || counter)
+    fn squeeze(&mut self) -> Vec<u8> {
+        let o = hash_sha([self.state.as_slice(), &self.counter.to_be_bytes()].concat());
+        self.counter += 1;
+        o
+    }
+
+    // uniformly random field element, rejecting the values >= P like FieldElement::random
+    pub fn challenge(&mut self) -> FieldElement {
+        loop {
+            let bytes = self.squeeze();
+            let value = u64::from_be_bytes(bytes[0..8].try_into().unwrap());
+            if value < P {
+                return FieldElement::new(value);
+            }
+        }
+    }
+
+    pub fn challenges(&mut self, count: usize) -> Vec<FieldElement> {
+        (0..count).map(|_| self.challenge()).collect()
+    }
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        let mut t1 = Transcript::new(b"test");
+        let mut t2 = Transcript::new(b"test");
+        t1.append(b"root");
+        t2.append(b"root");
+        assert_eq!(t1.challenges(3), t2.challenges(3));
+        assert_eq!(t1.challenge(), t2.challenge());
+    }
+
+    #[test]
+    fn test_depends_on_inputs() {
+        let mut t1 = Transcript::new(b"test");
+        let mut t2 = Transcript::new(b"test");
+        let mut t3 = Transcript::new(b"other");
+        t1.append(b"root");
+        t2.append(b"toor");
+        t3.append(b"root");
+        let c1 = t1.challenge();
+        assert_ne!(c1, t2.challenge());
+        assert_ne!(c1, t3.challenge());
+        // successive challenges differ
+        assert_ne!(c1, t1.challenge());
+    }
+}
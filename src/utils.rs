@@ -10,23 +10,22 @@ pub fn get_pseudorandom_indices(seed: &[u8], modulus: u64, count: usize, exclude
         hasher.update(&data[size_update..]);
         data.extend_from_slice(&hasher.finalize());
     }
-    if exclude_multiples_of == 0 {
-        (0..count)
+    match (modulus * exclude_multiples_of.saturating_sub(1)).checked_div(exclude_multiples_of) {
+        None => (0..count)
             .map(|i| u64::from_be_bytes(data[i * 8..(i + 1) * 8].try_into().unwrap()) % modulus)
-            .collect()
-    } else {
-        let real_modulus = modulus * (exclude_multiples_of - 1) / exclude_multiples_of;
-        let o: Vec<u64> = (0..count)
-            .map(|i| u64::from_be_bytes(data[i * 8..(i + 1) * 8].try_into().unwrap()) % real_modulus)
-            .collect();
-        o.iter().map(|&x| x + 1 + x / (exclude_multiples_of - 1)).collect()
+            .collect(),
+        Some(real_modulus) => {
+            let o: Vec<u64> = (0..count)
+                .map(|i| u64::from_be_bytes(data[i * 8..(i + 1) * 8].try_into().unwrap()) % real_modulus)
+                .collect();
+            o.iter().map(|&x| x + 1 + x / (exclude_multiples_of - 1)).collect()
+        }
     }
 }
 
 // tests
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
@@ -40,9 +39,9 @@ mod tests {
         println!("{:?}", o);
 
         assert_eq!(o.len(), count);
-        for i in 0..count {
-            assert!(o[i] < modulus);
-            assert_ne!(o[i] % exclude_multiples_of, 0);
+        for x in o {
+            assert!(x < modulus);
+            assert_ne!(x % exclude_multiples_of, 0);
         }
         
     }
@@ -0,0 +1,300 @@
+// verifier of the proofs of prover.rs
+// the constraints are checked once, at the out of domain point z, and FRI proves the DEEP
+// composition is low degree, which ties the out of domain evaluations to the committed trace
+
+use crate::air::Air;
+use crate::domain::Domain;
+use crate::field::FieldElement;
+use crate::fri::{first_layer_queries, is_well_formed, verify_low_degree_proof};
+use crate::merkle_tree::verify_branch;
+use crate::prover::{
+    check_air, composition_coefficients, composition_degree, compose_at, decode_row, deep_at, num_composition_segments,
+    quotient_degrees, quotients_at, recombine_segments, trace_degree_bound, transcript_label, StarkProof,
+    extension_factor,
+};
+use crate::transcript::Transcript;
+
+// check the proof that a trace satisfying the air exists
+pub fn verify<A: Air>(air: &A, proof: &StarkProof) -> bool {
+    let steps = air.steps();
+    let zk = proof.zk;
+    if check_air(air, zk).is_err() {
+        return false;
+    }
+    let num_main_columns = air.num_columns();
+    let num_aux_columns = air.num_aux_columns();
+    let num_columns = num_main_columns + num_aux_columns;
+    if proof.aux_trace_root.is_some() != (num_aux_columns > 0) {
+        return false;
+    }
+    // in zk mode the composition segments are followed by the random polynomial masking them
+    let segments = num_composition_segments(air, zk);
+    let composition_columns = segments + usize::from(zk);
+    if proof.ood.trace.len() != num_columns || proof.ood.trace_next.len() != num_columns
+        || proof.ood.composition.len() != composition_columns {
+        return false;
+    }
+    if !is_well_formed(&proof.fri_proof) {
+        return false;
+    }
+
+    let precision = steps * extension_factor as usize;
+    let domain = Domain::new(precision.trailing_zeros());
+    let g1 = Domain::root_of_unity(steps.trailing_zeros());
+    let offset = FieldElement::generator();
+
+    // replay the transcript of the prover
+    let mut transcript = Transcript::new(transcript_label(zk));
+    transcript.append(&proof.trace_root);
+    let challenges = transcript.challenges(air.num_challenges());
+    if let Some(aux_trace_root) = &proof.aux_trace_root {
+        transcript.append(aux_trace_root);
+    }
+    let coefficients = composition_coefficients(&mut transcript, air.num_transition_constraints() + air.assertions().len());
+    transcript.append(&proof.composition_root);
+    let z = transcript.challenge();
+    transcript.append_field_elements(&proof.ood.trace);
+    transcript.append_field_elements(&proof.ood.trace_next);
+    transcript.append_field_elements(&proof.ood.composition);
+    let deep_coefficients = transcript.challenges(2 * num_columns + composition_columns);
+    transcript.append(&proof.deep_root);
+
+    // the constraints at z, from the out of domain evaluations of the trace, against the
+    // composition recombined from its segments
+    let quotients = match quotients_at(air, &proof.ood, &challenges, z) {
+        Some(quotients) => quotients,
+        None => return false,
+    };
+    let composition = recombine_segments(&proof.ood.composition[..segments], z, steps);
+    if compose_at(&quotients, &quotient_degrees(air, zk), composition_degree(air, zk), &coefficients, z) != composition {
+        return false;
+    }
+
+    // the DEEP composition is low degree
+    if !verify_low_degree_proof(&proof.deep_root, &domain.generator(), &proof.fri_proof, trace_degree_bound(steps, zk), 0) {
+        return false;
+    }
+
+    // and matches the committed trace and composition where FRI opened it
+    let queries = first_layer_queries(&proof.fri_proof, precision, 0);
+    let aux_branches = if num_aux_columns > 0 { queries.len() } else { 0 };
+    if queries.len() != proof.trace_branches.len() || queries.len() != proof.composition_branches.len()
+        || aux_branches != proof.aux_trace_branches.len() {
+        return false;
+    }
+    for (i, (position, deep_value)) in queries.iter().enumerate() {
+        let trace_branch = &proof.trace_branches[i];
+        let composition_branch = &proof.composition_branches[i];
+        if !verify_branch(&proof.trace_root, *position, trace_branch)
+            || !verify_branch(&proof.composition_root, *position, composition_branch) {
+            return false;
+        }
+
+        let (mut trace, composition) = match (decode_row(&trace_branch[0], num_main_columns, zk), decode_row(&composition_branch[0], composition_columns, zk)) {
+            (Some(trace), Some(composition)) => (trace, composition),
+            _ => return false,
+        };
+        if let Some(aux_trace_root) = &proof.aux_trace_root {
+            let aux_branch = &proof.aux_trace_branches[i];
+            if !verify_branch(aux_trace_root, *position, aux_branch) {
+                return false;
+            }
+            match decode_row(&aux_branch[0], num_aux_columns, zk) {
+                Some(aux) => trace.extend(aux),
+                None => return false,
+            }
+        }
+        let deep_value = match FieldElement::from_bytes(deep_value) {
+            Ok(deep_value) => deep_value,
+            Err(_) => return false,
+        };
+
+        let x = offset * domain.powers()[*position];
+        let expected = deep_at(&proof.ood, &trace, &composition, (x - z).inv(), (x - g1 * z).inv(), &deep_coefficients);
+        if expected != deep_value {
+            return false;
+        }
+    }
+
+    true
+}
+
+//tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::air::TransitionDomain;
+    use crate::assertion::Assertion;
+    use crate::prover::{extend_trace, prove, prove_zk};
+    use rand_core::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    // a counter x_(i+1) = x_i + 1 next to a column alternating between 0 and 1
+    struct CounterAir {
+        steps: usize,
+        assertions: Vec<Assertion>,
+    }
+
+    impl Air for CounterAir {
+        fn steps(&self) -> usize {
+            self.steps
+        }
+
+        fn num_columns(&self) -> usize {
+            2
+        }
+
+        fn transition_degrees(&self) -> Vec<usize> {
+            vec![1, 1]
+        }
+
+        fn evaluate_transition(&self, current: &[FieldElement], next: &[FieldElement], _periodic: &[FieldElement], _challenges: &[FieldElement]) -> Vec<FieldElement> {
+            let one = FieldElement::new(1);
+            vec![next[0] - current[0] - one, next[1] + current[1] - one]
+        }
+
+        fn assertions(&self) -> Vec<Assertion> {
+            self.assertions.clone()
+        }
+    }
+
+    fn counter_trace(steps: usize) -> Vec<Vec<FieldElement>> {
+        vec![
+            (0..steps as u64).map(FieldElement::new).collect(),
+            (0..steps as u64).map(|i| FieldElement::new(i % 2)).collect(),
+        ]
+    }
+
+    #[test]
+    fn test_verify_assertions() {
+        let steps = 32;
+        let values = |v: &[u64]| v.iter().map(|x| FieldElement::new(*x)).collect::<Vec<_>>();
+        let air = CounterAir {
+            steps,
+            assertions: vec![
+                Assertion::sequence(0, 3, 5, values(&[3, 8, 13, 18])),
+                Assertion::periodic(1, 1, 2, values(&[1])),
+                Assertion::periodic(0, 0, 8, values(&[0, 8, 16, 24])),
+            ],
+        };
+        let proof = prove(&air, &counter_trace(steps)).unwrap();
+        assert!(verify(&air, &proof));
+
+        // the same proof doesn't verify other assertions
+        let air = CounterAir { steps, assertions: vec![Assertion::periodic(0, 0, 8, values(&[0, 8, 16, 25]))] };
+        assert!(!verify(&air, &proof));
+    }
+
+    #[test]
+    fn test_prove_false_assertion() {
+        // the trace doesn't satisfy the assertion, so the composition isn't low degree and
+        // the out of domain check fails
+        let steps = 32;
+        let air = CounterAir { steps, assertions: vec![Assertion::single(0, 4, FieldElement::new(5))] };
+        let proof = prove(&air, &counter_trace(steps)).unwrap();
+        assert!(!verify(&air, &proof));
+    }
+
+    // a counter a, a column b = 2 a on every 4th step, and a column c tripling on steps 2..6
+    // b and c are free on the other steps
+    struct SelectorAir {
+        steps: usize,
+    }
+
+    impl Air for SelectorAir {
+        fn steps(&self) -> usize {
+            self.steps
+        }
+
+        fn num_columns(&self) -> usize {
+            3
+        }
+
+        fn transition_degrees(&self) -> Vec<usize> {
+            vec![1, 1, 1]
+        }
+
+        fn evaluate_transition(&self, current: &[FieldElement], next: &[FieldElement], _periodic: &[FieldElement], _challenges: &[FieldElement]) -> Vec<FieldElement> {
+            vec![
+                next[0] - current[0] - FieldElement::new(1),
+                current[1] - FieldElement::new(2) * current[0],
+                next[2] - FieldElement::new(3) * current[2],
+            ]
+        }
+
+        fn assertions(&self) -> Vec<Assertion> {
+            vec![Assertion::single(0, 0, FieldElement::new(0))]
+        }
+
+        fn transition_domains(&self) -> Vec<TransitionDomain> {
+            vec![
+                TransitionDomain::AllButLast,
+                TransitionDomain::Every { stride: 4, offset: 0 },
+                TransitionDomain::Range { start: 2, end: 6 },
+            ]
+        }
+    }
+
+    fn selector_trace(steps: usize) -> Vec<Vec<FieldElement>> {
+        let mut c = (0..steps as u64).map(|i| FieldElement::new(i * 7 + 100)).collect::<Vec<_>>();
+        for i in 2..6 {
+            c[i + 1] = FieldElement::new(3) * c[i];
+        }
+        vec![
+            (0..steps as u64).map(FieldElement::new).collect(),
+            (0..steps as u64).map(|i| FieldElement::new(if i % 4 == 0 { 2 * i } else { i * i + 5 })).collect(),
+            c,
+        ]
+    }
+
+    #[test]
+    fn test_transition_domains() {
+        let steps = 32;
+        let air = SelectorAir { steps };
+        assert!(verify(&air, &prove(&air, &selector_trace(steps)).unwrap()));
+
+        // the constraints don't apply outside of their domains
+        let mut trace = selector_trace(steps);
+        trace[1][5] += FieldElement::new(1);
+        trace[2][7] += FieldElement::new(1);
+        assert!(verify(&air, &prove(&air, &trace).unwrap()));
+
+        // but do inside
+        let mut trace = selector_trace(steps);
+        trace[1][8] += FieldElement::new(1);
+        assert!(!verify(&air, &prove(&air, &trace).unwrap()));
+        let mut trace = selector_trace(steps);
+        trace[2][6] += FieldElement::new(1);
+        assert!(!verify(&air, &prove(&air, &trace).unwrap()));
+    }
+
+    #[test]
+    fn test_zk() {
+        let steps = 256;
+        let air = CounterAir { steps, assertions: vec![Assertion::single(0, 4, FieldElement::new(4))] };
+        let mut rng = ChaCha20Rng::seed_from_u64(42);
+        let proof = prove_zk(&air, &counter_trace(steps), &mut rng).unwrap();
+        assert!(proof.zk);
+        assert!(verify(&air, &proof));
+
+        // the opened rows are salted, and differ from those of the unblinded trace
+        let columns = extend_trace(&counter_trace(steps));
+        let queries = first_layer_queries(&proof.fri_proof, steps * extension_factor as usize, 0);
+        let (position, _) = queries[0];
+        let row = decode_row(&proof.trace_branches[0][0], 2, true).unwrap();
+        assert_ne!(row, vec![columns[0][position], columns[1][position]]);
+
+        // a second proof of the same trace opens other values
+        let other = prove_zk(&air, &counter_trace(steps), &mut rng).unwrap();
+        assert_ne!(proof.trace_root, other.trace_root);
+        assert_ne!(proof.ood.trace, other.ood.trace);
+
+        // the zk flag is bound to the proof
+        let mut proof = proof;
+        proof.zk = false;
+        assert!(!verify(&air, &proof));
+
+        let air = CounterAir { steps, assertions: vec![Assertion::single(0, 4, FieldElement::new(5))] };
+        assert!(!verify(&air, &prove_zk(&air, &counter_trace(steps), &mut rng).unwrap()));
+    }
+}